@@ -31,6 +31,9 @@ impl Attributes {
     /// Indicates a long file name entry.
     pub const LFN: u8 = Self::READ_ONLY | Self::HIDDEN | Self::SYSTEM | Self::VOLUME;
 
+    /// The attributes that can be changed on an existing entry.
+    pub const MODIFIABLE: u8 = Self::READ_ONLY | Self::HIDDEN | Self::SYSTEM | Self::ARCHIVE;
+
     /// Create a new Attributes from a raw u8 value.
     pub fn new(value: u8) -> Attributes {
         Attributes(value)
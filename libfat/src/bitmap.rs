@@ -0,0 +1,323 @@
+//! exFAT allocation bitmap managment.
+
+use super::cluster::Cluster;
+use super::filesystem::FatFileSystem;
+use super::table::FatClusterIter;
+use super::utils::FileSystemIterator;
+use super::FatError;
+use super::FatFileSystemResult;
+use spin::Mutex;
+use storage_device::StorageDevice;
+
+/// The count of bitmap blocks kept in the bitmap cache.
+const BITMAP_CACHE_ENTRY_COUNT: usize = 8;
+
+/// The size of a block of the bitmap cache.
+const BITMAP_BLOCK_SIZE: u64 = crate::MINIMAL_BLOCK_SIZE as u64;
+
+#[derive(Clone, Copy)]
+/// Represent a block of the allocation bitmap kept in memory.
+struct BitmapCacheEntry {
+    /// The index of the block inside the bitmap. (None if the entry is unused)
+    block_index: Option<u64>,
+
+    /// The offset of the block on the storage device.
+    storage_offset: u64,
+
+    /// Set when the block was modified and need to be written back.
+    is_dirty: bool,
+
+    /// The value of the access counter when the block was last used.
+    last_access: u64,
+
+    /// The content of the block.
+    data: [u8; crate::MINIMAL_BLOCK_SIZE],
+}
+
+/// Represent a write-back cache of the allocation bitmap blocks.
+struct BitmapCache {
+    /// The blocks kept in memory.
+    entries: [BitmapCacheEntry; BITMAP_CACHE_ENTRY_COUNT],
+
+    /// Counter used to find the least recently used block.
+    access_counter: u64,
+}
+
+impl BitmapCache {
+    /// Create a new empty bitmap cache.
+    fn new() -> Self {
+        BitmapCache {
+            entries: [BitmapCacheEntry {
+                block_index: None,
+                storage_offset: 0,
+                is_dirty: false,
+                last_access: 0,
+                data: [0x0; crate::MINIMAL_BLOCK_SIZE],
+            }; BITMAP_CACHE_ENTRY_COUNT],
+            access_counter: 0,
+        }
+    }
+
+    /// Write back a modified block to the storage device.
+    fn flush_entry<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        entry_index: usize,
+    ) -> FatFileSystemResult<()> {
+        let entry = &mut self.entries[entry_index];
+
+        if entry.block_index.is_some() && entry.is_dirty {
+            fs.storage_device
+                .lock()
+                .write(entry.storage_offset, &entry.data)
+                .or(Err(FatError::WriteFailed))?;
+
+            entry.is_dirty = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// Represent the exFAT allocation bitmap.
+/// Every bit of the bitmap track the allocation state of a cluster of the cluster heap.
+/// NOTE: The bitmap is read and written through a write-back cache of its blocks.
+pub(crate) struct AllocationBitmap {
+    /// The first cluster of the bitmap.
+    start_cluster: Cluster,
+
+    /// The size in bytes of the bitmap.
+    length: u64,
+
+    /// The write-back cache of the bitmap blocks.
+    cache: Mutex<BitmapCache>,
+}
+
+impl AllocationBitmap {
+    /// Create a new allocation bitmap representation.
+    pub fn new(start_cluster: Cluster, length: u64) -> Self {
+        AllocationBitmap {
+            start_cluster,
+            length,
+            cache: Mutex::new(BitmapCache::new()),
+        }
+    }
+
+    /// Create an allocation bitmap representation used on FAT12/FAT16/FAT32 filesystems.
+    pub fn empty() -> Self {
+        Self::new(Cluster(0), 0)
+    }
+
+    /// Compute the index of the bit tracking a given cluster.
+    fn get_bit_index(cluster: Cluster) -> FatFileSystemResult<u64> {
+        // The cluster heap starts at the cluster 2.
+        if cluster.0 < 2 {
+            return Err(FatError::Custom {
+                name: "cluster outside of the cluster heap",
+            });
+        }
+
+        Ok(u64::from(cluster.0 - 2))
+    }
+
+    /// Compute the offset on the storage device of a block of the bitmap.
+    fn get_storage_offset<S: StorageDevice>(
+        &self,
+        fs: &FatFileSystem<S>,
+        block_index: u64,
+    ) -> FatFileSystemResult<u64> {
+        let byte_index = block_index * BITMAP_BLOCK_SIZE;
+        if byte_index >= self.length {
+            return Err(FatError::NotFound);
+        }
+
+        let cluster_size = u64::from(fs.boot_record.bytes_per_cluster());
+        let cluster = FatClusterIter::new(fs, self.start_cluster)
+            .nth(fs, (byte_index / cluster_size) as usize)
+            .ok_or(FatError::ReadFailed)?;
+
+        Ok(fs.partition_start + cluster.to_data_bytes_offset(fs) + byte_index % cluster_size)
+    }
+
+    /// Get the cache entry holding a given block of the bitmap, loading it if needed.
+    fn get_entry<'b, S: StorageDevice>(
+        &self,
+        fs: &FatFileSystem<S>,
+        cache: &'b mut BitmapCache,
+        block_index: u64,
+    ) -> FatFileSystemResult<&'b mut BitmapCacheEntry> {
+        cache.access_counter += 1;
+
+        let entry_index = match cache
+            .entries
+            .iter()
+            .position(|entry| entry.block_index == Some(block_index))
+        {
+            Some(entry_index) => entry_index,
+            None => {
+                // Evict the least recently used block
+                let mut entry_index = 0;
+                for (index, entry) in cache.entries.iter().enumerate() {
+                    if entry.last_access < cache.entries[entry_index].last_access {
+                        entry_index = index;
+                    }
+                }
+
+                cache.flush_entry(fs, entry_index)?;
+
+                let storage_offset = self.get_storage_offset(fs, block_index)?;
+
+                let entry = &mut cache.entries[entry_index];
+                entry.block_index = None;
+
+                fs.storage_device
+                    .lock()
+                    .read(storage_offset, &mut entry.data)
+                    .or(Err(FatError::ReadFailed))?;
+
+                entry.block_index = Some(block_index);
+                entry.storage_offset = storage_offset;
+                entry_index
+            }
+        };
+
+        let entry = &mut cache.entries[entry_index];
+        entry.last_access = cache.access_counter;
+
+        Ok(entry)
+    }
+
+    /// Check if a given cluster is marked as free.
+    pub fn is_free<S: StorageDevice>(
+        &self,
+        fs: &FatFileSystem<S>,
+        cluster: Cluster,
+    ) -> FatFileSystemResult<bool> {
+        let index = Self::get_bit_index(cluster)?;
+        let byte_index = index / 8;
+
+        let mut cache = self.cache.lock();
+        let entry = self.get_entry(fs, &mut cache, byte_index / BITMAP_BLOCK_SIZE)?;
+
+        Ok((entry.data[(byte_index % BITMAP_BLOCK_SIZE) as usize] & (1 << (index % 8))) == 0)
+    }
+
+    /// Mark a given cluster as used or free.
+    pub fn set_used<S: StorageDevice>(
+        &self,
+        fs: &FatFileSystem<S>,
+        cluster: Cluster,
+        used: bool,
+    ) -> FatFileSystemResult<()> {
+        let index = Self::get_bit_index(cluster)?;
+        let byte_index = index / 8;
+
+        let mut cache = self.cache.lock();
+        let entry = self.get_entry(fs, &mut cache, byte_index / BITMAP_BLOCK_SIZE)?;
+        let value = &mut entry.data[(byte_index % BITMAP_BLOCK_SIZE) as usize];
+
+        if used {
+            *value |= 1 << (index % 8);
+        } else {
+            *value &= !(1 << (index % 8));
+        }
+
+        entry.is_dirty = true;
+
+        Ok(())
+    }
+
+    /// Find the first cluster marked as free (or used when ``free`` isn't set) in the ``[cluster, end_cluster)`` range.
+    /// NOTE: The bitmap is scanned a byte at a time, bytes without any matching cluster are skipped.
+    pub fn find<S: StorageDevice>(
+        &self,
+        fs: &FatFileSystem<S>,
+        cluster: Cluster,
+        end_cluster: u32,
+        free: bool,
+    ) -> FatFileSystemResult<Option<Cluster>> {
+        let start_index = Self::get_bit_index(cluster)?;
+        let end_index = u64::from(end_cluster.saturating_sub(2));
+
+        let mut cache = self.cache.lock();
+        let mut index = start_index;
+
+        while index < end_index {
+            let byte_index = index / 8;
+            let entry = self.get_entry(fs, &mut cache, byte_index / BITMAP_BLOCK_SIZE)?;
+
+            // Scan the rest of the block.
+            let mut byte_offset = byte_index % BITMAP_BLOCK_SIZE;
+            while byte_offset < BITMAP_BLOCK_SIZE && index < end_index {
+                let mut value = entry.data[byte_offset as usize];
+
+                // Free clusters are the set bits of the inverted byte.
+                if free {
+                    value = !value;
+                }
+
+                // Ignore the bits before the index.
+                let matching_bits = value & (0xFFu8 << (index % 8));
+
+                if matching_bits != 0 {
+                    let matching_index = (index & !7) + u64::from(matching_bits.trailing_zeros());
+                    if matching_index >= end_index {
+                        return Ok(None);
+                    }
+
+                    return Ok(Some(Cluster(matching_index as u32 + 2)));
+                }
+
+                index = (index & !7) + 8;
+                byte_offset += 1;
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Write back all the modified blocks of the bitmap to the storage device.
+    pub fn flush<S: StorageDevice>(&self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        let mut cache = self.cache.lock();
+
+        for entry_index in 0..cache.entries.len() {
+            cache.flush_entry(fs, entry_index)?;
+        }
+
+        Ok(())
+    }
+
+    /// Compute the count of free clusters.
+    pub fn get_free_cluster_count<S: StorageDevice>(
+        &self,
+        fs: &FatFileSystem<S>,
+    ) -> FatFileSystemResult<u32> {
+        let cluster_count = u64::from(fs.boot_record.cluster_count - 2);
+
+        let mut cache = self.cache.lock();
+        let mut index = 0;
+        let mut res = 0;
+
+        while index < cluster_count {
+            let byte_index = index / 8;
+            let entry = self.get_entry(fs, &mut cache, byte_index / BITMAP_BLOCK_SIZE)?;
+
+            let mut byte_offset = byte_index % BITMAP_BLOCK_SIZE;
+            while byte_offset < BITMAP_BLOCK_SIZE && index < cluster_count {
+                let mut value = entry.data[byte_offset as usize];
+
+                // Ignore the bits after the last cluster.
+                let bit_count = core::cmp::min(8, cluster_count - index);
+                if bit_count < 8 {
+                    value |= 0xFFu8 << bit_count;
+                }
+
+                res += value.count_zeros();
+                index += bit_count;
+                byte_offset += 1;
+            }
+        }
+
+        Ok(res)
+    }
+}
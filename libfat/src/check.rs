@@ -0,0 +1,950 @@
+//! FAT filesystem checker.
+//!
+//! The checker walks every directory from the root and checks the cluster chains of their entries against the FAT.
+//! As no allocation is possible, the cluster usage is tracked in a buffer given by the caller (see ``cluster_map_len``)
+//! and the inconsistencies found are reported one by one to a callback.
+//! NOTE: exFAT filesystems aren't supported by the checker.
+
+use super::cluster::Cluster;
+use super::directory::raw_dir_entry::FatDirEntry;
+use super::directory::raw_dir_entry_iterator::FatDirEntryIterator;
+use super::filesystem::FatFileSystem;
+use super::name::{LongFileName, ShortFileName};
+use super::table;
+use super::table::FatValue;
+use super::utils;
+use super::utils::FileSystemIterator;
+use super::FatError;
+use super::FatFileSystemResult;
+use super::FatFsType;
+use storage_device::StorageDevice;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Select what the checker does with the inconsistencies found.
+pub enum CheckMode {
+    /// Only report the inconsistencies.
+    Verify,
+
+    /// Report the inconsistencies and repair them.
+    Repair,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Identify a directory entry in a check finding.
+pub struct CheckEntry {
+    /// The first cluster of the directory holding the entry (0 for the FAT12/FAT16 root directory).
+    pub directory_cluster: u32,
+
+    /// The raw 8.3 name of the entry (blank for the root directory).
+    pub short_name: [u8; ShortFileName::MAX_LEN],
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Represent an inconsistency found by the checker.
+pub enum CheckFinding {
+    /// The first cluster of an entry isn't a valid data cluster.
+    /// Repaired by truncating the file to zero (see ``InvalidDirectory`` for directories).
+    InvalidFirstCluster {
+        /// The entry using the cluster.
+        entry: CheckEntry,
+
+        /// The first cluster of the entry.
+        cluster: u32,
+    },
+
+    /// The FAT value of a cluster of a chain is free, bad or out of range.
+    /// Repaired by ending the chain at this cluster.
+    InvalidChain {
+        /// The entry owning the chain.
+        entry: CheckEntry,
+
+        /// The cluster with the invalid FAT value.
+        cluster: u32,
+    },
+
+    /// A cluster of a chain is already used by another chain (or earlier in the same chain).
+    /// Repaired by ending the chain before this cluster, or like ``InvalidFirstCluster`` if it's the first cluster of the chain.
+    CrossLinkedCluster {
+        /// The entry owning the chain.
+        entry: CheckEntry,
+
+        /// The cluster already in use.
+        cluster: u32,
+    },
+
+    /// A directory cannot be read as its first cluster is invalid or cross-linked.
+    /// Repaired by deleting the directory entry, the clusters of its children are then released as lost chains.
+    InvalidDirectory {
+        /// The directory.
+        entry: CheckEntry,
+    },
+
+    /// A directory is nested deeper than ``MAX_DIRECTORY_DEPTH``, its children aren't checked.
+    /// Not repaired, as the clusters of the children aren't known, the lost chains aren't searched.
+    DirectoryTooDeep {
+        /// The directory.
+        entry: CheckEntry,
+    },
+
+    /// The size of a file doesn't match the length of its cluster chain.
+    /// Repaired by freeing the extra clusters or by reducing the file size to the chain length.
+    SizeMismatch {
+        /// The file.
+        entry: CheckEntry,
+
+        /// The size of the file.
+        file_size: u32,
+
+        /// The count of clusters in the chain of the file.
+        cluster_count: u32,
+    },
+
+    /// The "." entry of a directory is missing or doesn't point to the directory.
+    /// Repaired by fixing the cluster of the entry if it exists.
+    InvalidDotEntry {
+        /// The directory.
+        entry: CheckEntry,
+    },
+
+    /// The ".." entry of a directory is missing or doesn't point to the parent directory.
+    /// Repaired by fixing the cluster of the entry if it exists.
+    InvalidDotDotEntry {
+        /// The directory.
+        entry: CheckEntry,
+    },
+
+    /// VFAT long entries that aren't followed by a matching 8.3 entry (broken sequence or bad checksum).
+    /// Repaired by deleting the long entries.
+    OrphanedLongFileName {
+        /// The first cluster of the directory holding the entries (0 for the FAT12/FAT16 root directory).
+        directory_cluster: u32,
+
+        /// The count of long entries.
+        entry_count: u32,
+    },
+
+    /// A cluster chain used in the FAT but not referenced by any entry.
+    /// Repaired by freeing the chain.
+    LostChain {
+        /// The first cluster of the chain.
+        start_cluster: u32,
+
+        /// The count of clusters in the chain.
+        cluster_count: u32,
+    },
+
+    /// A FAT copy differs from the active FAT.
+    /// Repaired by writing the active FAT over the copy.
+    FatMirrorMismatch {
+        /// The index of the FAT copy.
+        fat_index: u32,
+
+        /// The first cluster with a differing entry.
+        first_cluster: u32,
+
+        /// The count of differing entries.
+        entry_count: u32,
+    },
+
+    /// The free cluster count of the FS Info doesn't match the FAT (FAT32 only).
+    /// Repaired by writing the actual count to the FS Info.
+    FreeClusterCountMismatch {
+        /// The free cluster count of the FS Info.
+        recorded: u32,
+
+        /// The free cluster count of the FAT.
+        actual: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Represent the statistics of a check.
+pub struct CheckSummary {
+    /// The count of directories found (root directory excluded).
+    pub directory_count: u32,
+
+    /// The count of files found.
+    pub file_count: u32,
+
+    /// The count of clusters used by the entries.
+    pub used_cluster_count: u32,
+
+    /// The count of free clusters after the check.
+    pub free_cluster_count: u32,
+
+    /// The count of inconsistencies reported.
+    pub finding_count: u32,
+}
+
+/// The max depth of the directories checked, the root directory being at depth 0.
+/// NOTE: This bounds the recursion of the checker.
+pub const MAX_DIRECTORY_DEPTH: u32 = 64;
+
+/// Marker of the clusters reached from a directory entry.
+const CLUSTER_USED: u8 = 0x1;
+
+/// Marker of the clusters referenced by a cluster of a lost chain.
+const CLUSTER_REFERENCED: u8 = 0x2;
+
+/// The count of bits used per cluster in the cluster map.
+const CLUSTER_MAP_BITS: usize = 2;
+
+/// Track the state of the clusters during a check.
+struct ClusterMap<'a> {
+    /// The state of the clusters.
+    data: &'a mut [u8],
+}
+
+impl<'a> ClusterMap<'a> {
+    /// Check if a marker is set on a given cluster.
+    fn is_set(&self, cluster: u32, marker: u8) -> bool {
+        let bit_index = cluster as usize * CLUSTER_MAP_BITS;
+
+        (self.data[bit_index / 8] >> (bit_index % 8)) & marker != 0
+    }
+
+    /// Set a marker on a given cluster.
+    fn set(&mut self, cluster: u32, marker: u8) {
+        let bit_index = cluster as usize * CLUSTER_MAP_BITS;
+
+        self.data[bit_index / 8] |= marker << (bit_index % 8);
+    }
+}
+
+/// Track a sequence of VFAT long entries waiting for their 8.3 entry.
+#[derive(Clone, Copy)]
+struct LongFileNameSequence {
+    /// The first long entry of the sequence.
+    first_entry: Option<FatDirEntry>,
+
+    /// The count of long entries in the sequence.
+    entry_count: u32,
+
+    /// The order of the next expected long entry.
+    next_order: u8,
+
+    /// The checksum of the 8.3 name in the long entries.
+    checksum: u8,
+}
+
+impl LongFileNameSequence {
+    /// Create an empty sequence.
+    fn empty() -> Self {
+        LongFileNameSequence {
+            first_entry: None,
+            entry_count: 0,
+            next_order: 0,
+            checksum: 0,
+        }
+    }
+}
+
+/// Hold the state of a check.
+struct Checker<'a, S: StorageDevice, F: FnMut(&CheckFinding)> {
+    /// The filesystem being checked.
+    fs: &'a FatFileSystem<S>,
+
+    /// Set if the inconsistencies must be repaired.
+    repair: bool,
+
+    /// The state of the clusters.
+    cluster_map: ClusterMap<'a>,
+
+    /// The callback receiving the findings.
+    report: F,
+
+    /// The statistics of the check.
+    summary: CheckSummary,
+
+    /// Set if some directories couldn't be checked.
+    is_incomplete: bool,
+}
+
+/// Get the size of the buffer needed to check a given filesystem.
+pub fn cluster_map_len<S: StorageDevice>(fs: &FatFileSystem<S>) -> usize {
+    utils::align_up(fs.boot_record.cluster_count as usize * CLUSTER_MAP_BITS, 8) / 8
+}
+
+/// Check the consistency of a FAT12/FAT16/FAT32 filesystem.
+///
+/// ``cluster_map`` must be at least ``cluster_map_len`` bytes long. Every inconsistency found is given to ``report``.
+/// In ``CheckMode::Repair``, the inconsistencies are repaired as they are found.
+/// NOTE: exFAT filesystems aren't supported, an error is returned for them.
+pub fn check_filesystem<S: StorageDevice, F: FnMut(&CheckFinding)>(
+    fs: &FatFileSystem<S>,
+    mode: CheckMode,
+    cluster_map: &mut [u8],
+    report: F,
+) -> FatFileSystemResult<CheckSummary> {
+    if fs.boot_record.fat_type == FatFsType::ExFat {
+        return Err(FatError::Custom {
+            name: "exFAT filesystems cannot be checked",
+        });
+    }
+
+    let cluster_map_len = cluster_map_len(fs);
+    if cluster_map.len() < cluster_map_len {
+        return Err(FatError::Custom {
+            name: "cluster map is too small",
+        });
+    }
+
+    let cluster_map = &mut cluster_map[..cluster_map_len];
+    for value in cluster_map.iter_mut() {
+        *value = 0;
+    }
+
+    let mut checker = Checker {
+        fs,
+        repair: mode == CheckMode::Repair,
+        cluster_map: ClusterMap { data: cluster_map },
+        report,
+        summary: CheckSummary::default(),
+        is_incomplete: false,
+    };
+
+    // The FAT copies are compared first as the repairs are written to all of them.
+    checker.check_fat_mirrors()?;
+    checker.check_root_directory()?;
+
+    // Without all the directories, chains still in use would be found as lost.
+    if !checker.is_incomplete {
+        checker.check_lost_chains()?;
+    }
+
+    checker.check_free_cluster_count()?;
+
+    if checker.repair {
+        fs.sync()?;
+    }
+
+    Ok(checker.summary)
+}
+
+impl<'a, S: StorageDevice, F: FnMut(&CheckFinding)> Checker<'a, S, F> {
+    /// Report a finding.
+    fn report(&mut self, finding: CheckFinding) {
+        self.summary.finding_count += 1;
+        (self.report)(&finding);
+    }
+
+    /// Check if a given cluster is a data cluster.
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.fs.boot_record.cluster_count
+    }
+
+    /// Check the root directory and all its children.
+    fn check_root_directory(&mut self) -> FatFileSystemResult<()> {
+        let root_directory = self.fs.get_root_directory();
+
+        // The FAT12/FAT16 root directory isn't stored in clusters.
+        if self.fs.boot_record.fat_type == FatFsType::Fat32 {
+            let entry = CheckEntry {
+                directory_cluster: 0,
+                short_name: [b' '; ShortFileName::MAX_LEN],
+            };
+
+            let root_cluster = root_directory.dir_info.start_cluster.0;
+
+            // Without a valid first cluster, nothing can be checked.
+            if self.check_chain(entry, root_cluster)?.is_none() {
+                return Ok(());
+            }
+
+            self.check_directory(root_directory.fat_dir_entry_iter(), root_cluster, None, 0)
+        } else {
+            self.check_directory(root_directory.fat_dir_entry_iter(), 0, None, 0)
+        }
+    }
+
+    /// Check the cluster chain of an entry and mark its clusters as used.
+    /// Return the length of the chain or None if the first cluster is invalid or already used.
+    fn check_chain(
+        &mut self,
+        entry: CheckEntry,
+        start_cluster: u32,
+    ) -> FatFileSystemResult<Option<u32>> {
+        if !self.is_valid_cluster(start_cluster) {
+            self.report(CheckFinding::InvalidFirstCluster {
+                entry,
+                cluster: start_cluster,
+            });
+            return Ok(None);
+        }
+
+        if self.cluster_map.is_set(start_cluster, CLUSTER_USED) {
+            self.report(CheckFinding::CrossLinkedCluster {
+                entry,
+                cluster: start_cluster,
+            });
+            return Ok(None);
+        }
+
+        let mut cluster = start_cluster;
+        let mut cluster_count = 0;
+
+        loop {
+            self.cluster_map.set(cluster, CLUSTER_USED);
+            self.summary.used_cluster_count += 1;
+            cluster_count += 1;
+
+            let next_cluster = match FatValue::get(self.fs, Cluster(cluster))? {
+                FatValue::EndOfChain => break,
+                FatValue::Data(next_cluster) if self.is_valid_cluster(next_cluster) => next_cluster,
+                _ => {
+                    self.report(CheckFinding::InvalidChain { entry, cluster });
+                    self.end_chain(cluster)?;
+                    break;
+                }
+            };
+
+            if self.cluster_map.is_set(next_cluster, CLUSTER_USED) {
+                self.report(CheckFinding::CrossLinkedCluster {
+                    entry,
+                    cluster: next_cluster,
+                });
+                self.end_chain(cluster)?;
+                break;
+            }
+
+            cluster = next_cluster;
+        }
+
+        Ok(Some(cluster_count))
+    }
+
+    /// Make a given cluster the end of its chain when repairing.
+    fn end_chain(&self, cluster: u32) -> FatFileSystemResult<()> {
+        if self.repair {
+            self.fs.invalidate_extents();
+            FatValue::put(self.fs, Cluster(cluster), FatValue::EndOfChain)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check the entries of a directory.
+    /// ``parent_cluster`` is the cluster expected in the ".." entry, None for the root directory.
+    /// ``depth`` is the depth of the directory, 0 for the root directory.
+    fn check_directory(
+        &mut self,
+        mut raw_iter: FatDirEntryIterator,
+        directory_cluster: u32,
+        parent_cluster: Option<u32>,
+        depth: u32,
+    ) -> FatFileSystemResult<()> {
+        let is_root_directory = parent_cluster.is_none();
+
+        // Entries of the root directory have ".." pointing to cluster 0.
+        let children_parent_cluster = if is_root_directory {
+            0
+        } else {
+            directory_cluster
+        };
+
+        let mut sequence = LongFileNameSequence::empty();
+        let mut entry_index = 0;
+
+        while let Some(raw_dir_entry) = raw_iter.next(self.fs) {
+            let raw_dir_entry = raw_dir_entry?;
+            let current_index = entry_index;
+            entry_index += 1;
+
+            // End of directory
+            if raw_dir_entry.is_free() {
+                break;
+            }
+
+            // Special entries (".", "..")
+            if let Some(parent_cluster) = parent_cluster {
+                if current_index < 2 {
+                    let is_valid_special_entry = self.check_special_entry(
+                        Some(raw_dir_entry),
+                        current_index == 1,
+                        directory_cluster,
+                        parent_cluster,
+                    )?;
+
+                    if is_valid_special_entry {
+                        continue;
+                    }
+                }
+            }
+
+            if raw_dir_entry.is_deleted() {
+                self.release_sequence(&mut sequence, directory_cluster, true)?;
+                continue;
+            }
+
+            if raw_dir_entry.is_long_file_name() {
+                self.check_long_file_name(&mut sequence, raw_dir_entry, directory_cluster)?;
+                continue;
+            }
+
+            let is_matching_sequence = sequence.first_entry.is_some()
+                && sequence.next_order == 0
+                && sequence.checksum == ShortFileName::checksum_lfn(&raw_dir_entry.data[0..11])
+                && !raw_dir_entry.attribute().is_volume();
+
+            if !is_matching_sequence {
+                self.release_sequence(&mut sequence, directory_cluster, true)?;
+            }
+
+            // Volume label and misplaced special entries
+            if raw_dir_entry.attribute().is_volume() || raw_dir_entry.data[0] == b'.' {
+                sequence = LongFileNameSequence::empty();
+                continue;
+            }
+
+            let is_deleted = self.check_entry(
+                raw_dir_entry,
+                directory_cluster,
+                children_parent_cluster,
+                depth + 1,
+            )?;
+
+            // Delete the long entries of a deleted entry.
+            if is_deleted {
+                self.release_sequence(&mut sequence, directory_cluster, false)?;
+            }
+
+            sequence = LongFileNameSequence::empty();
+        }
+
+        self.release_sequence(&mut sequence, directory_cluster, true)?;
+
+        // The special entries are missing
+        if let Some(parent_cluster) = parent_cluster {
+            for index in entry_index..2 {
+                self.check_special_entry(None, index == 1, directory_cluster, parent_cluster)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the "." or ".." entry of a directory.
+    /// Return true if the entry is a special entry.
+    fn check_special_entry(
+        &mut self,
+        raw_dir_entry: Option<FatDirEntry>,
+        is_dot_dot: bool,
+        directory_cluster: u32,
+        parent_cluster: u32,
+    ) -> FatFileSystemResult<bool> {
+        let expected_name: &[u8; ShortFileName::MAX_LEN] = if is_dot_dot {
+            b"..         "
+        } else {
+            b".          "
+        };
+
+        let expected_cluster = if is_dot_dot {
+            parent_cluster
+        } else {
+            directory_cluster
+        };
+
+        let is_special_entry = match raw_dir_entry {
+            Some(raw_dir_entry) => {
+                !raw_dir_entry.is_long_file_name()
+                    && raw_dir_entry.attribute().is_directory()
+                    && &raw_dir_entry.data[0..11] == expected_name
+            }
+            None => false,
+        };
+
+        if is_special_entry {
+            let mut raw_dir_entry = raw_dir_entry.unwrap();
+            let cluster = raw_dir_entry.get_cluster().0;
+
+            // Some implementations use the FAT32 root directory cluster instead of 0.
+            let is_root_cluster = is_dot_dot
+                && expected_cluster == 0
+                && self.fs.boot_record.fat_type == FatFsType::Fat32
+                && cluster == self.fs.boot_record.root_dir_childs_cluster().0;
+
+            if cluster == expected_cluster || is_root_cluster {
+                return Ok(true);
+            }
+
+            if self.repair {
+                raw_dir_entry.set_cluster(Cluster(expected_cluster));
+                raw_dir_entry.flush(self.fs)?;
+            }
+        }
+
+        let entry = CheckEntry {
+            directory_cluster,
+            short_name: *expected_name,
+        };
+
+        if is_dot_dot {
+            self.report(CheckFinding::InvalidDotDotEntry { entry });
+        } else {
+            self.report(CheckFinding::InvalidDotEntry { entry });
+        }
+
+        Ok(is_special_entry)
+    }
+
+    /// Add a VFAT long entry to the current sequence.
+    fn check_long_file_name(
+        &mut self,
+        sequence: &mut LongFileNameSequence,
+        raw_dir_entry: FatDirEntry,
+        directory_cluster: u32,
+    ) -> FatFileSystemResult<()> {
+        let lfn_entry = raw_dir_entry.as_lfn_entry();
+        let order = lfn_entry.order_entry & !0x40;
+        let is_last_entry = (lfn_entry.order_entry & 0x40) != 0;
+        let is_valid_order = order != 0 && order as usize <= LongFileName::MAX_ENTRY_COUNT;
+
+        // The last long entry starts a new sequence.
+        if is_last_entry {
+            self.release_sequence(sequence, directory_cluster, true)?;
+        }
+
+        let is_expected_entry = is_valid_order
+            && (is_last_entry
+                || (order == sequence.next_order && lfn_entry.lfn_checksum == sequence.checksum));
+
+        if sequence.first_entry.is_none() {
+            sequence.first_entry = Some(raw_dir_entry);
+            sequence.checksum = lfn_entry.lfn_checksum;
+        }
+
+        sequence.entry_count += 1;
+
+        // A broken sequence cannot be completed anymore, it ends at the next 8.3 entry.
+        sequence.next_order = if is_expected_entry { order - 1 } else { 0xFF };
+
+        Ok(())
+    }
+
+    /// Drop the current long entries sequence, reporting it and deleting its entries if needed.
+    fn release_sequence(
+        &mut self,
+        sequence: &mut LongFileNameSequence,
+        directory_cluster: u32,
+        is_orphaned: bool,
+    ) -> FatFileSystemResult<()> {
+        if let Some(first_entry) = sequence.first_entry {
+            if is_orphaned {
+                self.report(CheckFinding::OrphanedLongFileName {
+                    directory_cluster,
+                    entry_count: sequence.entry_count,
+                });
+            }
+
+            if self.repair {
+                self.delete_entries(first_entry, sequence.entry_count)?;
+            }
+        }
+
+        *sequence = LongFileNameSequence::empty();
+
+        Ok(())
+    }
+
+    /// Mark ``entry_count`` raw entries as deleted, starting at a given one.
+    fn delete_entries(
+        &self,
+        first_entry: FatDirEntry,
+        entry_count: u32,
+    ) -> FatFileSystemResult<()> {
+        let is_in_old_root_directory = match self.fs.boot_record.fat_type {
+            FatFsType::Fat12 | FatFsType::Fat16 => first_entry.entry_cluster.0 == 0,
+            _ => false,
+        };
+
+        let mut raw_iter = FatDirEntryIterator::new(
+            self.fs,
+            first_entry.entry_cluster,
+            first_entry.entry_cluster_offset,
+            first_entry.entry_offset,
+            is_in_old_root_directory,
+            false,
+        );
+
+        for _ in 0..entry_count {
+            let mut raw_dir_entry = raw_iter.next(self.fs).ok_or(FatError::ReadFailed)??;
+
+            raw_dir_entry.set_deleted();
+            raw_dir_entry.flush(self.fs)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check a file or a directory entry.
+    /// ``depth`` is the depth of the entry, 1 for the entries of the root directory.
+    /// Return true if the entry was deleted by the repair.
+    fn check_entry(
+        &mut self,
+        mut raw_dir_entry: FatDirEntry,
+        directory_cluster: u32,
+        parent_cluster: u32,
+        depth: u32,
+    ) -> FatFileSystemResult<bool> {
+        let mut short_name = [0x0u8; ShortFileName::MAX_LEN];
+        short_name.copy_from_slice(&raw_dir_entry.data[0..11]);
+
+        let entry = CheckEntry {
+            directory_cluster,
+            short_name,
+        };
+
+        let is_directory = raw_dir_entry.attribute().is_directory();
+        let start_cluster = raw_dir_entry.get_cluster().0;
+
+        if is_directory {
+            self.summary.directory_count += 1;
+        } else {
+            self.summary.file_count += 1;
+
+            // Empty file
+            if start_cluster == 0 {
+                self.check_file_size(raw_dir_entry, entry, 0)?;
+                return Ok(false);
+            }
+        }
+
+        let cluster_count = match self.check_chain(entry, start_cluster)? {
+            Some(cluster_count) => cluster_count,
+            None => {
+                if is_directory {
+                    self.report(CheckFinding::InvalidDirectory { entry });
+                }
+
+                if !self.repair {
+                    return Ok(false);
+                }
+
+                if is_directory {
+                    raw_dir_entry.set_deleted();
+                } else {
+                    raw_dir_entry.set_cluster(Cluster(0));
+                    raw_dir_entry.set_file_size(0);
+                }
+                raw_dir_entry.flush(self.fs)?;
+
+                return Ok(is_directory);
+            }
+        };
+
+        if is_directory {
+            if depth > MAX_DIRECTORY_DEPTH {
+                self.report(CheckFinding::DirectoryTooDeep { entry });
+                self.is_incomplete = true;
+                return Ok(false);
+            }
+
+            let raw_iter =
+                FatDirEntryIterator::new(self.fs, Cluster(start_cluster), 0, 0, false, false);
+
+            self.check_directory(raw_iter, start_cluster, Some(parent_cluster), depth)?;
+        } else {
+            self.check_file_size(raw_dir_entry, entry, cluster_count)?;
+        }
+
+        Ok(false)
+    }
+
+    /// Check the size of a file against the length of its cluster chain.
+    fn check_file_size(
+        &mut self,
+        mut raw_dir_entry: FatDirEntry,
+        entry: CheckEntry,
+        cluster_count: u32,
+    ) -> FatFileSystemResult<()> {
+        let cluster_size = u64::from(self.fs.boot_record.bytes_per_cluster());
+        let file_size = raw_dir_entry.get_file_size();
+        let expected_cluster_count =
+            utils::align_up(u64::from(file_size), cluster_size) / cluster_size;
+
+        if expected_cluster_count == u64::from(cluster_count) {
+            return Ok(());
+        }
+
+        self.report(CheckFinding::SizeMismatch {
+            entry,
+            file_size,
+            cluster_count,
+        });
+
+        if !self.repair {
+            return Ok(());
+        }
+
+        if expected_cluster_count > u64::from(cluster_count) {
+            let chain_size = u64::from(cluster_count) * cluster_size;
+            raw_dir_entry.set_file_size(chain_size.min(0xFFFF_FFFF) as u32);
+            return raw_dir_entry.flush(self.fs);
+        }
+
+        let start_cluster = raw_dir_entry.get_cluster();
+
+        if expected_cluster_count == 0 {
+            self.fs.free_cluster(start_cluster, None)?;
+            raw_dir_entry.set_cluster(Cluster(0));
+            raw_dir_entry.set_file_size(0);
+            return raw_dir_entry.flush(self.fs);
+        }
+
+        let mut last_cluster = start_cluster;
+        for _ in 1..expected_cluster_count {
+            match FatValue::get(self.fs, last_cluster)? {
+                FatValue::Data(next_cluster) => last_cluster = Cluster(next_cluster),
+                _ => return Err(FatError::InvalidPartition),
+            }
+        }
+
+        match FatValue::get(self.fs, last_cluster)? {
+            FatValue::Data(next_cluster) => self
+                .fs
+                .free_cluster(Cluster(next_cluster), Some(last_cluster)),
+            _ => Err(FatError::InvalidPartition),
+        }
+    }
+
+    /// Find the cluster chains that aren't referenced by any entry.
+    fn check_lost_chains(&mut self) -> FatFileSystemResult<()> {
+        let cluster_count = self.fs.boot_record.cluster_count;
+
+        // Mark the lost clusters referenced by other lost clusters to find the start of the chains.
+        for cluster in 2..cluster_count {
+            if self.cluster_map.is_set(cluster, CLUSTER_USED) {
+                continue;
+            }
+
+            if let FatValue::Data(next_cluster) = FatValue::get(self.fs, Cluster(cluster))? {
+                if self.is_valid_cluster(next_cluster) {
+                    self.cluster_map.set(next_cluster, CLUSTER_REFERENCED);
+                }
+            }
+        }
+
+        // The second pass releases the chains without start (loops).
+        for is_loop_pass in &[false, true] {
+            for cluster in 2..cluster_count {
+                if self.cluster_map.is_set(cluster, CLUSTER_USED)
+                    || (!*is_loop_pass && self.cluster_map.is_set(cluster, CLUSTER_REFERENCED))
+                {
+                    continue;
+                }
+
+                match FatValue::get(self.fs, Cluster(cluster))? {
+                    FatValue::Free | FatValue::Bad => continue,
+                    _ => self.release_lost_chain(cluster)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Report a lost cluster chain and free it if needed.
+    fn release_lost_chain(&mut self, start_cluster: u32) -> FatFileSystemResult<()> {
+        let mut cluster = start_cluster;
+        let mut cluster_count = 0;
+
+        loop {
+            self.cluster_map.set(cluster, CLUSTER_USED);
+            cluster_count += 1;
+
+            let value = FatValue::get(self.fs, Cluster(cluster))?;
+
+            if self.repair {
+                self.fs.invalidate_extents();
+                FatValue::put(self.fs, Cluster(cluster), FatValue::Free)?;
+            }
+
+            match value {
+                FatValue::Data(next_cluster)
+                    if self.is_valid_cluster(next_cluster)
+                        && !self.cluster_map.is_set(next_cluster, CLUSTER_USED) =>
+                {
+                    cluster = next_cluster
+                }
+                _ => break,
+            }
+        }
+
+        self.report(CheckFinding::LostChain {
+            start_cluster,
+            cluster_count,
+        });
+
+        if self.repair {
+            let free_cluster_count = self.fs.get_free_cluster_count();
+            self.fs
+                .set_free_cluster_count(free_cluster_count.saturating_add(cluster_count));
+        }
+
+        Ok(())
+    }
+
+    /// Compare the FAT copies with the active FAT.
+    fn check_fat_mirrors(&mut self) -> FatFileSystemResult<()> {
+        // The copies aren't kept up to date when the mirroring is disabled.
+        if !self.fs.is_fat_mirroring_enabled() {
+            return Ok(());
+        }
+
+        // Make sure the FATs are up to date on the storage device.
+        self.fs.fat_cache.lock().flush(self.fs)?;
+
+        let active_fat = u32::from(self.fs.get_active_fat());
+
+        for fat_index in 0..u32::from(self.fs.get_fats_count()) {
+            if fat_index == active_fat {
+                continue;
+            }
+
+            let mut first_cluster = None;
+            let entry_count =
+                table::compare_fat(self.fs, active_fat, fat_index, |cluster, _, _| {
+                    if first_cluster.is_none() {
+                        first_cluster = Some(cluster.0);
+                    }
+                })?;
+
+            if let Some(first_cluster) = first_cluster {
+                self.report(CheckFinding::FatMirrorMismatch {
+                    fat_index,
+                    first_cluster,
+                    entry_count,
+                });
+
+                if self.repair {
+                    table::copy_fat(self.fs, active_fat, fat_index)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compare the free cluster count of the FS Info with the FAT.
+    fn check_free_cluster_count(&mut self) -> FatFileSystemResult<()> {
+        let actual = table::get_free_cluster_count(self.fs)?;
+        self.summary.free_cluster_count = actual;
+
+        // Only FAT32 stores the free cluster count.
+        if self.fs.boot_record.fat_type != FatFsType::Fat32 {
+            return Ok(());
+        }
+
+        let recorded = self.fs.get_free_cluster_count();
+
+        if recorded != actual {
+            self.report(CheckFinding::FreeClusterCountMismatch { recorded, actual });
+
+            if self.repair {
+                self.fs.set_free_cluster_count(actual);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,14 @@
 //! FAT datetime.
 
+/// A source of the current date and time used to stamp directory entries.
+/// This is implemented by the users of the library (a kernel RTC, the host clock...).
+pub trait Clock: Sync {
+    /// Return the current local datetime.
+    fn now(&self) -> FatDateTime;
+}
+
 /// Represent a FAT date time
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct FatDateTime {
     /// The year of the datetime.
     year: u16,
@@ -26,17 +33,17 @@ pub struct FatDateTime {
 }
 
 impl FatDateTime {
-    /// The amount of days in every leap year indexed per months.
-    const DAYS: [[u16; 12]; 4] = [
-        [0, 31, 60, 91, 121, 152, 182, 213, 244, 274, 305, 335],
-        [366, 397, 425, 456, 486, 517, 547, 578, 609, 639, 670, 700],
-        [
-            731, 762, 790, 821, 851, 882, 912, 943, 974, 1004, 1035, 1065,
-        ],
-        [
-            1096, 1127, 1155, 1186, 1216, 1247, 1277, 1308, 1339, 1369, 1400, 1430,
-        ],
-    ];
+    /// The amount of days before every months in a non leap year.
+    const DAYS_BEFORE_MONTH: [u16; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+
+    /// The amount of seconds in a day.
+    const SECONDS_PER_DAY: u64 = 86_400;
+
+    /// The UNIX timestamp of the FAT epoch (1980-01-01 00:00:00).
+    const MIN_UNIX_TIME: u64 = 315_532_800;
+
+    /// The UNIX timestamp of the last datetime representable (2107-12-31 23:59:59).
+    const MAX_UNIX_TIME: u64 = 4_354_819_199;
 
     /// Create a new datetime
     pub fn new(
@@ -59,33 +66,216 @@ impl FatDateTime {
         }
     }
 
+    /// Create a new datetime from raw FAT date and time values.
+    /// NOTE: ``raw_tenths`` is in 10 ms units (0 to 199) as stored on disk.
+    pub fn from_raw(raw_date: u16, raw_time: u16, raw_tenths: u8) -> Self {
+        let seconds = ((raw_time & 0x1f) << 1) as u8 + raw_tenths / 100;
+        let minutes = ((raw_time >> 5) & 0x3f) as u8;
+        let hour = ((raw_time >> 11) & 0x1f) as u8;
+
+        let day = (raw_date & 0x1f) as u8;
+        let month = ((raw_date >> 5) & 0xf) as u8;
+        let year = (raw_date >> 9) & 0x7f;
+
+        let tenths = (raw_tenths % 100) / 10;
+
+        FatDateTime::new(1980 + year, month, day, hour, minutes, seconds, tenths)
+    }
+
+    /// Return the datetime clamped to the range representable by FAT (1980-01-01 00:00:00 to 2107-12-31 23:59:59).
+    fn clamped(&self) -> Self {
+        if self.year < 1980 {
+            FatDateTime::new(1980, 1, 1, 0, 0, 0, 0)
+        } else if self.year > 2107 {
+            FatDateTime::new(2107, 12, 31, 23, 59, 59, 9)
+        } else {
+            *self
+        }
+    }
+
+    /// Convert the date to a raw FAT date value.
+    /// NOTE: Dates outside of the 1980-2107 range are clamped to it.
+    pub fn to_raw_date(&self) -> u16 {
+        let datetime = self.clamped();
+
+        ((datetime.year - 1980) << 9) | (u16::from(datetime.month) << 5) | u16::from(datetime.day)
+    }
+
+    /// Convert the time to a raw FAT time value.
+    /// NOTE: The seconds are only stored with a 2 seconds resolution, the rest is kept by ``to_raw_tenths``.
+    pub fn to_raw_time(&self) -> u16 {
+        let datetime = self.clamped();
+
+        (u16::from(datetime.hour) << 11)
+            | (u16::from(datetime.minutes) << 5)
+            | u16::from(datetime.seconds >> 1)
+    }
+
+    /// Convert the sub 2 seconds part of the time to a raw FAT fine resolution value (10 ms units, values from 0 to 199).
+    pub fn to_raw_tenths(&self) -> u8 {
+        let datetime = self.clamped();
+
+        (datetime.seconds & 1) * 100 + datetime.tenths * 10
+    }
+
+    /// Check if a given year is a leap year.
+    fn is_leap_year(year: u64) -> bool {
+        (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+    }
+
+    /// Return the amount of days in a given year.
+    fn days_in_year(year: u64) -> u64 {
+        if Self::is_leap_year(year) {
+            366
+        } else {
+            365
+        }
+    }
+
+    /// Return the amount of days in a given month (1 to 12) of a given year.
+    fn days_in_month(year: u64, month: u64) -> u64 {
+        match month {
+            2 if Self::is_leap_year(year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    /// Create a new datetime from a UNIX timestamp.
+    /// NOTE: Timestamps outside of the 1980-2107 range are clamped to it.
+    pub fn from_unix_time(timestamp: u64) -> Self {
+        let timestamp = core::cmp::min(
+            core::cmp::max(timestamp, Self::MIN_UNIX_TIME),
+            Self::MAX_UNIX_TIME,
+        );
+
+        let mut days = timestamp / Self::SECONDS_PER_DAY;
+        let day_seconds = timestamp % Self::SECONDS_PER_DAY;
+
+        let mut year = 1970;
+        while days >= Self::days_in_year(year) {
+            days -= Self::days_in_year(year);
+            year += 1;
+        }
+
+        let mut month = 1;
+        while days >= Self::days_in_month(year, month) {
+            days -= Self::days_in_month(year, month);
+            month += 1;
+        }
+
+        FatDateTime::new(
+            year as u16,
+            month as u8,
+            days as u8 + 1,
+            (day_seconds / 3600) as u8,
+            ((day_seconds / 60) % 60) as u8,
+            (day_seconds % 60) as u8,
+            0,
+        )
+    }
+
     /// Convert the FAT datetime to a UNIX timestamp.
-    /// NOTE: This only support the 2000-2099 range. If something outside this range is provided, it will return an UNIX epoch.
+    /// NOTE: If the datetime is invalid, it will return an UNIX epoch.
     pub fn to_unix_time(&self) -> u64 {
-        // TODO: support other ranges than 2000-2099
-        // BODY: If a date that is outside the 2000-2099 range is provided, we should support it.
-        // BODY: In practice, this only apply to the range 1980-1999.
-        if self.year > 2099 || self.year < 2000 {
+        let year = u64::from(self.year);
+        let month = u64::from(self.month);
+        let day = u64::from(self.day);
+
+        if year < 1980 || month == 0 || month > 12 || day == 0 {
             return 0;
         }
 
-        let year = u64::from(self.year) % 100;
-        let month = u64::from(self.month) - 1;
-        let day = u64::from(self.day) - 1;
+        // The amount of leap years between year 1 and the given year minus the ones before 1970.
+        let leap_days = (year - 1) / 4 - (year - 1) / 100 + (year - 1) / 400 - 477;
+
+        let mut days = (year - 1970) * 365
+            + leap_days
+            + u64::from(Self::DAYS_BEFORE_MONTH[month as usize - 1])
+            + day
+            - 1;
+
+        if month > 2 && Self::is_leap_year(year) {
+            days += 1;
+        }
 
         let hour = u64::from(self.hour);
         let minutes = u64::from(self.minutes);
         let seconds = u64::from(self.seconds);
 
-        946_684_800
-            + (((year / 4 * (365 * 4 + 1)
-                + u64::from(Self::DAYS[year as usize % 4][month as usize])
-                + day)
-                * 24
-                + hour)
-                * 60
-                + minutes)
-                * 60
-            + seconds
+        days * Self::SECONDS_PER_DAY + (hour * 60 + minutes) * 60 + seconds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FatDateTime;
+
+    /// Check that a datetime converts to a given UNIX timestamp and back.
+    fn check_round_trip(datetime: FatDateTime, timestamp: u64) {
+        assert_eq!(datetime.to_unix_time(), timestamp);
+
+        let converted = FatDateTime::from_unix_time(timestamp);
+        assert_eq!(converted.to_raw_date(), datetime.to_raw_date());
+        assert_eq!(converted.to_raw_time(), datetime.to_raw_time());
+        assert_eq!(converted.to_raw_tenths(), datetime.to_raw_tenths());
+    }
+
+    #[test]
+    fn unix_time_round_trip() {
+        check_round_trip(FatDateTime::new(1980, 1, 1, 0, 0, 0, 0), 315_532_800);
+        check_round_trip(FatDateTime::new(1999, 12, 31, 23, 59, 58, 0), 946_684_798);
+        check_round_trip(FatDateTime::new(2000, 2, 29, 12, 30, 10, 0), 951_827_410);
+        check_round_trip(FatDateTime::new(2100, 3, 1, 0, 0, 0, 0), 4_107_542_400);
+        check_round_trip(FatDateTime::new(2107, 12, 31, 23, 59, 58, 0), 4_354_819_198);
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let datetime = FatDateTime::new(2019, 5, 22, 13, 37, 43, 5);
+        let converted = FatDateTime::from_raw(
+            datetime.to_raw_date(),
+            datetime.to_raw_time(),
+            datetime.to_raw_tenths(),
+        );
+
+        assert_eq!(converted.to_unix_time(), datetime.to_unix_time());
+        assert_eq!(converted.to_raw_tenths(), datetime.to_raw_tenths());
+    }
+
+    #[test]
+    fn unix_time_clamping() {
+        let min = FatDateTime::new(1980, 1, 1, 0, 0, 0, 0);
+        let max = FatDateTime::new(2107, 12, 31, 23, 59, 59, 0);
+
+        assert_eq!(
+            FatDateTime::from_unix_time(0).to_unix_time(),
+            min.to_unix_time()
+        );
+        assert_eq!(
+            FatDateTime::from_unix_time(5_000_000_000).to_unix_time(),
+            max.to_unix_time()
+        );
+        assert_eq!(FatDateTime::new(1979, 12, 31, 0, 0, 0, 0).to_unix_time(), 0);
+    }
+
+    #[test]
+    fn raw_clamping() {
+        let min = FatDateTime::new(1980, 1, 1, 0, 0, 0, 0);
+        let max = FatDateTime::new(2107, 12, 31, 23, 59, 59, 9);
+
+        // An unset RTC reporting the UNIX epoch.
+        let before = FatDateTime::new(1970, 6, 15, 12, 0, 0, 0);
+        assert_eq!(before.to_raw_date(), min.to_raw_date());
+        assert_eq!(before.to_raw_time(), min.to_raw_time());
+        assert_eq!(before.to_raw_tenths(), min.to_raw_tenths());
+
+        let after = FatDateTime::new(2108, 1, 1, 0, 0, 0, 0);
+        assert_eq!(after.to_raw_date(), max.to_raw_date());
+        assert_eq!(after.to_raw_time(), max.to_raw_time());
+        assert_eq!(after.to_raw_tenths(), max.to_raw_tenths());
+
+        assert_eq!(max.to_raw_date(), (127 << 9) | (12 << 5) | 31);
     }
 }
@@ -30,6 +30,9 @@ pub(crate) struct DirectoryEntryRawInfo {
 
     /// The count of raw entries used by the child entry.
     pub entry_count: u32,
+
+    /// Marker on entries present in an exFAT directory without FAT chain.
+    pub parent_no_fat_chain: bool,
 }
 
 impl DirectoryEntryRawInfo {
@@ -40,6 +43,7 @@ impl DirectoryEntryRawInfo {
         first_entry_offset: u64,
         entry_count: u32,
         in_old_fat_root_directory: bool,
+        parent_no_fat_chain: bool,
     ) -> Self {
         DirectoryEntryRawInfo {
             parent_cluster,
@@ -47,6 +51,7 @@ impl DirectoryEntryRawInfo {
             first_entry_offset,
             entry_count,
             in_old_fat_root_directory,
+            parent_no_fat_chain,
         }
     }
 }
@@ -70,7 +75,13 @@ pub struct DirectoryEntry {
     pub last_modification_timestamp: u64,
 
     /// The file size of the entry.
-    pub file_size: u32,
+    pub file_size: u64,
+
+    /// The size of the data actually written in the entry (exFAT only, equals to ``file_size`` otherwise).
+    pub(crate) valid_data_length: u64,
+
+    /// Marker on exFAT entries with contiguous clusters not described by the FAT.
+    pub(crate) no_fat_chain: bool,
 
     /// The file name of the entry.
     pub file_name: ArrayString<[u8; Self::MAX_FILE_NAME_LEN_UNICODE]>,
@@ -80,7 +91,7 @@ pub struct DirectoryEntry {
 }
 
 impl DirectoryEntry {
-    /// The max size of a VFAT long name.
+    /// The max size of a VFAT long name in UTF-16 code units.
     pub const MAX_FILE_NAME_LEN: usize = 255;
 
     /// The max size of a VFAT long name encoded as Unicode.
@@ -99,7 +110,9 @@ impl DirectoryEntry {
             creation_timestamp: sfn_entry.get_creation_datetime().to_unix_time(),
             last_access_timestamp: sfn_entry.get_last_access_date().to_unix_time(),
             last_modification_timestamp: sfn_entry.get_modification_datetime().to_unix_time(),
-            file_size: sfn_entry.get_file_size(),
+            file_size: u64::from(sfn_entry.get_file_size()),
+            valid_data_length: u64::from(sfn_entry.get_file_size()),
+            no_fat_chain: false,
             file_name,
             attribute: sfn_entry.attribute(),
         }
@@ -118,6 +131,7 @@ impl DirectoryEntryRawInfo {
             self.first_entry_cluster_offset,
             self.first_entry_offset,
             self.in_old_fat_root_directory,
+            self.parent_no_fat_chain,
         );
 
         let mut i = 0;
@@ -8,10 +8,12 @@ use storage_device::StorageDevice;
 
 use super::dir_entry::DirectoryEntry;
 use super::dir_entry::DirectoryEntryRawInfo;
+use super::entry_set::ExFatEntrySet;
 use super::raw_dir_entry::FatDirEntry;
 use super::raw_dir_entry_iterator::FatDirEntryIterator;
 use crate::filesystem::FatFileSystem;
 use crate::utils::FileSystemIterator;
+use crate::FatFsType;
 
 /// Represent a directory entries iterator.
 pub struct DirectoryEntryIterator {
@@ -19,16 +21,80 @@ pub struct DirectoryEntryIterator {
     pub(crate) raw_iter: FatDirEntryIterator,
 }
 
+impl DirectoryEntryIterator {
+    /// Read the next exFAT entry set and convert it to a directory entry.
+    fn next_exfat<S: StorageDevice>(
+        &mut self,
+        filesystem: &FatFileSystem<S>,
+    ) -> Option<FatFileSystemResult<DirectoryEntry>> {
+        while let Some(entry) = self.raw_iter.next(filesystem) {
+            if let Err(error) = entry {
+                return Some(Err(error));
+            }
+
+            let entry = entry.unwrap();
+
+            // End of directory
+            if entry.is_free() {
+                break;
+            }
+
+            // Skip unused entries and entries that aren't describing files (bitmap, up-case table, label...)
+            if !entry.is_exfat_in_use() || entry.get_first_byte() != FatDirEntry::EXFAT_FILE {
+                continue;
+            }
+
+            let mut entry_set = ExFatEntrySet::empty();
+            entry_set.push(entry);
+
+            for _ in 0..entry_set.secondary_count() {
+                match self.raw_iter.next(filesystem)? {
+                    Ok(secondary_entry) => {
+                        if !entry_set.push(secondary_entry) {
+                            break;
+                        }
+                    }
+                    Err(error) => return Some(Err(error)),
+                }
+            }
+
+            if !entry_set.is_valid() {
+                warn!("Invalid exFAT entry set found, skipping it");
+                continue;
+            }
+
+            let raw_info = DirectoryEntryRawInfo::new(
+                entry.entry_cluster,
+                entry.entry_cluster_offset,
+                entry.entry_offset,
+                entry_set.len() as u32,
+                false,
+                self.raw_iter.no_fat_chain,
+            );
+
+            return Some(Ok(entry_set.to_directory_entry(Some(raw_info))));
+        }
+
+        None
+    }
+}
+
 impl<S: StorageDevice> FileSystemIterator<S> for DirectoryEntryIterator {
     type Item = FatFileSystemResult<DirectoryEntry>;
     fn next(
         &mut self,
         filesystem: &FatFileSystem<S>,
     ) -> Option<FatFileSystemResult<DirectoryEntry>> {
+        if filesystem.boot_record.fat_type == FatFsType::ExFat {
+            return self.next_exfat(filesystem);
+        }
+
         let mut next_is_end_entry = false;
         let mut first_raw_dir_entry: Option<FatDirEntry> = None;
         let mut entry_count = 0;
         let mut lfn_index: i32 = 0;
+        let mut lfn_name = [0x0u16; LongFileName::MAX_LEN * LongFileName::MAX_ENTRY_COUNT];
+        let mut lfn_name_len = 0;
         let mut file_name = ArrayString::<[_; DirectoryEntry::MAX_FILE_NAME_LEN_UNICODE]>::new();
 
         while let Some(entry) = self.raw_iter.next(filesystem) {
@@ -50,7 +116,7 @@ impl<S: StorageDevice> FileSystemIterator<S> for DirectoryEntryIterator {
             // Deleted entry? Clear everything and continue
             if entry.is_deleted() {
                 lfn_index = 0;
-                file_name.clear();
+                lfn_name_len = 0;
                 first_raw_dir_entry = None;
                 entry_count = 0;
 
@@ -63,24 +129,17 @@ impl<S: StorageDevice> FileSystemIterator<S> for DirectoryEntryIterator {
 
                 if (lfn.order_entry & 0x40) != 0 {
                     lfn_index = i32::from(lfn.order_entry ^ 0x40);
+                    lfn_name_len = 0;
                 }
 
-                let mut part = ArrayString::<[_; LongFileName::MAX_LEN_UNICODE]>::new();
-                // FIXME: Custom Iterator to catches those errors
-                let raw_name = lfn_entry.chars().unwrap();
-                for c in &raw_name {
-                    if *c == '\x00' {
-                        break;
-                    }
-                    part.push(*c);
-                }
+                // Entries are stored in reverse order, put the part at its position in the name.
+                if lfn_index >= 1 && lfn_index as usize <= LongFileName::MAX_ENTRY_COUNT {
+                    let part_start = (lfn_index as usize - 1) * LongFileName::MAX_LEN;
+                    let part_end = part_start + LongFileName::MAX_LEN;
 
-                // We do some kind of push_front by hand
-                // FIXME: this is dirty
-                let tmp = file_name;
-                file_name.clear();
-                file_name.push_str(part.as_str());
-                file_name.push_str(tmp.as_str());
+                    lfn_name[part_start..part_end].copy_from_slice(&lfn_entry.as_contents());
+                    lfn_name_len = core::cmp::max(lfn_name_len, part_end);
+                }
 
                 let index_minus_one = lfn_index - 1;
 
@@ -94,10 +153,22 @@ impl<S: StorageDevice> FileSystemIterator<S> for DirectoryEntryIterator {
             }
 
             if !entry.attribute().is_volume() {
-                if !next_is_end_entry {
-                    // discard everything that could have previously be done
-                    file_name.clear();
-
+                if next_is_end_entry {
+                    // The name ends at the first null character, unpaired surrogates are replaced.
+                    let lfn_name = lfn_name[..lfn_name_len]
+                        .iter()
+                        .cloned()
+                        .take_while(|c| *c != 0);
+
+                    for c in core::char::decode_utf16(lfn_name) {
+                        if file_name
+                            .try_push(c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                } else {
                     let raw_name = entry.short_name().unwrap().chars();
                     for c in raw_name.iter().take(8) {
                         if *c == ' ' {
@@ -124,9 +195,6 @@ impl<S: StorageDevice> FileSystemIterator<S> for DirectoryEntryIterator {
                         )
                         .unwrap();
                 }
-                if let Some(end_char_index) = file_name.find('\0') {
-                    file_name.truncate(end_char_index);
-                }
 
                 let first_raw_dir_entry = first_raw_dir_entry.unwrap();
 
@@ -139,19 +207,22 @@ impl<S: StorageDevice> FileSystemIterator<S> for DirectoryEntryIterator {
                         first_raw_dir_entry.entry_offset,
                         entry_count,
                         self.raw_iter.cluster_iter.is_none(),
+                        self.raw_iter.no_fat_chain,
                     )),
                     creation_timestamp: entry.get_creation_datetime().to_unix_time(),
                     last_access_timestamp: entry.get_last_access_date().to_unix_time(),
                     last_modification_timestamp: entry.get_modification_datetime().to_unix_time(),
-                    file_size: entry.get_file_size(),
+                    file_size: u64::from(entry.get_file_size()),
+                    valid_data_length: u64::from(entry.get_file_size()),
+                    no_fat_chain: false,
                     file_name,
                     attribute: entry.attribute(),
                 }));
             }
 
             lfn_index = 0;
+            lfn_name_len = 0;
             next_is_end_entry = false;
-            file_name.clear();
             first_raw_dir_entry = None;
             entry_count = 0;
         }
@@ -0,0 +1,419 @@
+//! exFAT directory entry sets.
+use arrayvec::{ArrayString, ArrayVec};
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::attribute::Attributes;
+use crate::cluster::Cluster;
+use crate::datetime::FatDateTime;
+use crate::filesystem::FatFileSystem;
+use crate::upcase::UpcaseTable;
+
+use crate::FatError;
+use crate::FatFileSystemResult;
+use storage_device::StorageDevice;
+
+use super::dir_entry::{DirectoryEntry, DirectoryEntryRawInfo};
+use super::raw_dir_entry::FatDirEntry;
+use super::raw_dir_entry_iterator::FatDirEntryIterator;
+
+use crate::utils::FileSystemIterator;
+
+/// Represent an exFAT entry set (a file entry followed by its stream extension and file name entries).
+pub(crate) struct ExFatEntrySet {
+    /// The raw entries of the set.
+    entries: ArrayVec<[FatDirEntry; ExFatEntrySet::MAX_ENTRIES]>,
+}
+
+impl ExFatEntrySet {
+    /// The max count of entries in a set that we support.
+    pub const MAX_ENTRIES: usize = 32;
+
+    /// The count of UTF-16 characters stored in a file name entry.
+    pub const NAME_CHARS_PER_ENTRY: usize = 15;
+
+    /// The max length of a file name in UTF-16 characters.
+    pub const MAX_NAME_LEN: usize = 255;
+
+    /// Create an empty entry set.
+    pub fn empty() -> Self {
+        ExFatEntrySet {
+            entries: ArrayVec::new(),
+        }
+    }
+
+    /// Create a new entry set in memory. The set isn't written until ``write`` is called.
+    pub fn new(
+        upcase_table: &UpcaseTable,
+        name: &str,
+        attribute: Attributes,
+        cluster: Cluster,
+        data_length: u64,
+    ) -> FatFileSystemResult<Self> {
+        let mut file_entry = [0x0u8; FatDirEntry::LEN];
+        file_entry[0] = FatDirEntry::EXFAT_FILE;
+        LittleEndian::write_u16(&mut file_entry[4..6], u16::from(attribute.get_value()));
+
+        let mut stream_entry = [0x0u8; FatDirEntry::LEN];
+        stream_entry[0] = FatDirEntry::EXFAT_STREAM_EXTENSION;
+        stream_entry[1] = FatDirEntry::EXFAT_ALLOCATION_POSSIBLE;
+
+        let mut res = Self::empty();
+        res.entries
+            .push(FatDirEntry::from_raw(&file_entry, Cluster(0), 0, 0));
+        res.entries
+            .push(FatDirEntry::from_raw(&stream_entry, Cluster(0), 0, 0));
+
+        res.set_first_cluster(cluster);
+        res.set_data_length(data_length);
+        res.set_valid_data_length(data_length);
+        res.set_name(upcase_table, name)?;
+
+        Ok(res)
+    }
+
+    /// Read an entry set from its raw informations.
+    pub fn read<S: StorageDevice>(
+        fs: &FatFileSystem<S>,
+        raw_info: &DirectoryEntryRawInfo,
+    ) -> FatFileSystemResult<Self> {
+        let mut entries_iter = FatDirEntryIterator::new(
+            fs,
+            raw_info.parent_cluster,
+            raw_info.first_entry_cluster_offset,
+            raw_info.first_entry_offset,
+            raw_info.in_old_fat_root_directory,
+            raw_info.parent_no_fat_chain,
+        );
+
+        let mut res = Self::empty();
+        for _ in 0..raw_info.entry_count {
+            let entry = entries_iter.next(fs).ok_or(FatError::ReadFailed)??;
+            if !res.push(entry) {
+                return Err(FatError::Custom {
+                    name: "exFAT entry set is too big",
+                });
+            }
+        }
+
+        if !res.is_valid() {
+            return Err(FatError::Custom {
+                name: "Invalid exFAT entry set",
+            });
+        }
+
+        Ok(res)
+    }
+
+    /// Add a raw entry at the end of the set. Return false if the set is full.
+    pub fn push(&mut self, entry: FatDirEntry) -> bool {
+        self.entries.try_push(entry).is_ok()
+    }
+
+    /// Return the count of raw entries in the set.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Return the first raw entry of the set.
+    pub fn first_entry(&self) -> &FatDirEntry {
+        &self.entries[0]
+    }
+
+    /// Return the count of secondary entries declared by the file entry.
+    pub fn secondary_count(&self) -> usize {
+        usize::from(self.entries[0].as_exfat_file_entry().secondary_count)
+    }
+
+    /// Check the structure and the checksum of the set.
+    pub fn is_valid(&self) -> bool {
+        if self.entries.len() < 3
+            || self.entries[0].get_first_byte() != FatDirEntry::EXFAT_FILE
+            || self.entries[1].get_first_byte() != FatDirEntry::EXFAT_STREAM_EXTENSION
+            || self.entries.len() != self.secondary_count() + 1
+        {
+            return false;
+        }
+
+        let name_entry_count = self
+            .entries
+            .iter()
+            .skip(2)
+            .take_while(|entry| entry.get_first_byte() == FatDirEntry::EXFAT_FILE_NAME)
+            .count();
+
+        if name_entry_count * Self::NAME_CHARS_PER_ENTRY < self.name_length() {
+            return false;
+        }
+
+        self.compute_checksum() == self.entries[0].as_exfat_file_entry().set_checksum.to_int()
+    }
+
+    /// Compute the checksum of the set.
+    fn compute_checksum(&self) -> u16 {
+        let mut checksum = 0u16;
+
+        for (entry_index, entry) in self.entries.iter().enumerate() {
+            for (index, value) in entry.data.iter().enumerate() {
+                // Skip the checksum field.
+                if entry_index == 0 && (index == 2 || index == 3) {
+                    continue;
+                }
+
+                checksum = checksum.rotate_right(1).wrapping_add(u16::from(*value));
+            }
+        }
+
+        checksum
+    }
+
+    /// Write the entries of the set at the position of the given raw entries iterator.
+    pub fn write<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        entries_iter: &mut FatDirEntryIterator,
+    ) -> FatFileSystemResult<()> {
+        for entry in self.entries.iter_mut() {
+            let target_entry = entries_iter.next(fs).ok_or(FatError::NoSpaceLeft)??;
+
+            entry.entry_cluster = target_entry.entry_cluster;
+            entry.entry_cluster_offset = target_entry.entry_cluster_offset;
+            entry.entry_offset = target_entry.entry_offset;
+        }
+
+        self.flush(fs)
+    }
+
+    /// Update the checksum of the set and write it to disk.
+    pub fn flush<S: StorageDevice>(&mut self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        let checksum = self.compute_checksum();
+        LittleEndian::write_u16(&mut self.entries[0].data[2..4], checksum);
+
+        for entry in self.entries.iter() {
+            entry.flush(fs)?;
+        }
+
+        Ok(())
+    }
+
+    /// Return the length of the file name in UTF-16 characters.
+    fn name_length(&self) -> usize {
+        usize::from(self.entries[1].as_exfat_stream_entry().name_length)
+    }
+
+    /// Replace the file name entries of the set.
+    pub fn set_name(&mut self, upcase_table: &UpcaseTable, name: &str) -> FatFileSystemResult<()> {
+        let mut utf16_name: ArrayVec<[u16; 256]> = ArrayVec::new();
+        for c in name.encode_utf16() {
+            if utf16_name.try_push(c).is_err() {
+                return Err(FatError::PathTooLong);
+            }
+        }
+
+        if utf16_name.is_empty() || utf16_name.len() > Self::MAX_NAME_LEN {
+            return Err(FatError::PathTooLong);
+        }
+
+        // Drop the previous name and anything following it.
+        self.entries.truncate(2);
+
+        for chunk in utf16_name.chunks(Self::NAME_CHARS_PER_ENTRY) {
+            let mut name_entry = [0x0u8; FatDirEntry::LEN];
+            name_entry[0] = FatDirEntry::EXFAT_FILE_NAME;
+
+            for (index, c) in chunk.iter().enumerate() {
+                let index = 2 + index * 2;
+                LittleEndian::write_u16(&mut name_entry[index..index + 2], *c);
+            }
+
+            self.entries
+                .push(FatDirEntry::from_raw(&name_entry, Cluster(0), 0, 0));
+        }
+
+        self.entries[0].data[1] = (self.entries.len() - 1) as u8;
+        self.entries[1].data[3] = utf16_name.len() as u8;
+        LittleEndian::write_u16(
+            &mut self.entries[1].data[4..6],
+            upcase_table.name_hash(&utf16_name),
+        );
+
+        Ok(())
+    }
+
+    /// Return the file name of the set.
+    pub fn file_name(&self) -> ArrayString<[u8; DirectoryEntry::MAX_FILE_NAME_LEN_UNICODE]> {
+        let mut utf16_name: ArrayVec<[u16; 256]> = ArrayVec::new();
+
+        for entry in self.entries.iter().skip(2) {
+            if entry.get_first_byte() != FatDirEntry::EXFAT_FILE_NAME {
+                break;
+            }
+
+            for c in entry.as_exfat_file_name_entry().file_name.iter() {
+                if utf16_name.len() == self.name_length() {
+                    break;
+                }
+
+                utf16_name.push(c.to_int());
+            }
+        }
+
+        let mut file_name = ArrayString::<[_; DirectoryEntry::MAX_FILE_NAME_LEN_UNICODE]>::new();
+        for c in core::char::decode_utf16(utf16_name.iter().cloned()) {
+            file_name.push(c.unwrap_or(core::char::REPLACEMENT_CHARACTER));
+        }
+
+        file_name
+    }
+
+    /// Return the attributes of the file.
+    pub fn attribute(&self) -> Attributes {
+        Attributes::new(self.entries[0].as_exfat_file_entry().file_attributes.to_int() as u8)
+    }
+
+    /// Set the attributes of the file.
+    pub fn set_attribute(&mut self, attribute: Attributes) {
+        LittleEndian::write_u16(
+            &mut self.entries[0].data[4..6],
+            u16::from(attribute.get_value()),
+        );
+    }
+
+    /// Return the first cluster of the file.
+    pub fn first_cluster(&self) -> Cluster {
+        Cluster(self.entries[1].as_exfat_stream_entry().first_cluster.to_int())
+    }
+
+    /// Set the first cluster of the file.
+    pub fn set_first_cluster(&mut self, cluster: Cluster) {
+        LittleEndian::write_u32(&mut self.entries[1].data[20..24], cluster.0);
+    }
+
+    /// Return the size of the file in bytes.
+    pub fn data_length(&self) -> u64 {
+        self.entries[1].as_exfat_stream_entry().data_length.to_int()
+    }
+
+    /// Set the size of the file in bytes.
+    pub fn set_data_length(&mut self, data_length: u64) {
+        LittleEndian::write_u64(&mut self.entries[1].data[24..32], data_length);
+    }
+
+    /// Return the size of the data actually written in the file.
+    pub fn valid_data_length(&self) -> u64 {
+        self.entries[1]
+            .as_exfat_stream_entry()
+            .valid_data_length
+            .to_int()
+    }
+
+    /// Set the size of the data actually written in the file.
+    pub fn set_valid_data_length(&mut self, valid_data_length: u64) {
+        LittleEndian::write_u64(&mut self.entries[1].data[8..16], valid_data_length);
+    }
+
+    /// Check if the clusters of the file are contiguous and not described by the FAT.
+    pub fn is_no_fat_chain(&self) -> bool {
+        (self.entries[1].as_exfat_stream_entry().general_secondary_flags
+            & FatDirEntry::EXFAT_NO_FAT_CHAIN)
+            != 0
+    }
+
+    /// Set if the clusters of the file are contiguous and not described by the FAT.
+    pub fn set_no_fat_chain(&mut self, no_fat_chain: bool) {
+        if no_fat_chain {
+            self.entries[1].data[1] |= FatDirEntry::EXFAT_NO_FAT_CHAIN;
+        } else {
+            self.entries[1].data[1] &= !FatDirEntry::EXFAT_NO_FAT_CHAIN;
+        }
+    }
+
+    /// Retrieve the creation datetime of the file.
+    pub fn get_creation_datetime(&self) -> FatDateTime {
+        let entry = self.entries[0].as_exfat_file_entry();
+        let timestamp = entry.create_timestamp.to_int();
+
+        FatDateTime::from_raw(
+            (timestamp >> 16) as u16,
+            timestamp as u16,
+            entry.create_10ms_increment,
+        )
+    }
+
+    /// Retrieve the last access datetime of the file.
+    pub fn get_last_access_datetime(&self) -> FatDateTime {
+        let timestamp = self.entries[0]
+            .as_exfat_file_entry()
+            .last_accessed_timestamp
+            .to_int();
+
+        FatDateTime::from_raw((timestamp >> 16) as u16, timestamp as u16, 0)
+    }
+
+    /// Retrieve the last modification datetime of the file.
+    pub fn get_modification_datetime(&self) -> FatDateTime {
+        let entry = self.entries[0].as_exfat_file_entry();
+        let timestamp = entry.last_modified_timestamp.to_int();
+
+        FatDateTime::from_raw(
+            (timestamp >> 16) as u16,
+            timestamp as u16,
+            entry.last_modified_10ms_increment,
+        )
+    }
+
+    /// Set the creation datetime of the file.
+    pub fn set_creation_datetime(&mut self, datetime: &FatDateTime) {
+        let data = &mut self.entries[0].data;
+        LittleEndian::write_u16(&mut data[8..10], datetime.to_raw_time());
+        LittleEndian::write_u16(&mut data[10..12], datetime.to_raw_date());
+        data[20] = datetime.to_raw_tenths();
+    }
+
+    /// Set the last access datetime of the file.
+    pub fn set_last_access_datetime(&mut self, datetime: &FatDateTime) {
+        let data = &mut self.entries[0].data;
+        LittleEndian::write_u16(&mut data[16..18], datetime.to_raw_time());
+        LittleEndian::write_u16(&mut data[18..20], datetime.to_raw_date());
+    }
+
+    /// Set the last modification datetime of the file.
+    pub fn set_modification_datetime(&mut self, datetime: &FatDateTime) {
+        let data = &mut self.entries[0].data;
+        LittleEndian::write_u16(&mut data[12..14], datetime.to_raw_time());
+        LittleEndian::write_u16(&mut data[14..16], datetime.to_raw_date());
+        data[21] = datetime.to_raw_tenths();
+    }
+
+    /// Create a directory entry from the set.
+    pub fn to_directory_entry(&self, raw_info: Option<DirectoryEntryRawInfo>) -> DirectoryEntry {
+        DirectoryEntry {
+            start_cluster: self.first_cluster(),
+            raw_info,
+            creation_timestamp: self.get_creation_datetime().to_unix_time(),
+            last_access_timestamp: self.get_last_access_datetime().to_unix_time(),
+            last_modification_timestamp: self.get_modification_datetime().to_unix_time(),
+            file_size: self.data_length(),
+            valid_data_length: self.valid_data_length(),
+            no_fat_chain: self.is_no_fat_chain(),
+            file_name: self.file_name(),
+            attribute: self.attribute(),
+        }
+    }
+
+    /// Write the cluster chain informations of a directory entry to its entry set on disk.
+    pub fn update_stream<S: StorageDevice>(
+        fs: &FatFileSystem<S>,
+        entry: &DirectoryEntry,
+    ) -> FatFileSystemResult<()> {
+        let raw_info = entry.raw_info.ok_or(FatError::Custom {
+            name: "Raw Info is missing ON A FILE",
+        })?;
+
+        let mut entry_set = Self::read(fs, &raw_info)?;
+        entry_set.set_first_cluster(entry.start_cluster);
+        entry_set.set_data_length(entry.file_size);
+        entry_set.set_valid_data_length(entry.valid_data_length);
+        entry_set.set_no_fat_chain(entry.no_fat_chain);
+        entry_set.flush(fs)
+    }
+}
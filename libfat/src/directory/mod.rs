@@ -1,6 +1,7 @@
 //! FAT directory managment.
 
 use arrayvec::ArrayString;
+use core::ops::Range;
 
 use crate::FatError;
 use crate::FatFileSystemResult;
@@ -8,9 +9,13 @@ use storage_device::StorageDevice;
 
 use super::attribute::Attributes;
 use super::cluster::Cluster;
+use super::extent::ExtentCache;
+use super::name::LongFileName;
 use super::name::ShortFileName;
 use super::name::ShortFileNameContext;
+use super::name::ShortFileNameGenerator;
 use super::offset_iter::ClusterOffsetIter;
+use super::table::FatClusterIter;
 use super::utils;
 
 use super::table;
@@ -20,12 +25,15 @@ use super::FatFsType;
 
 pub mod dir_entry;
 pub mod dir_entry_iterator;
+pub(crate) mod entry_set;
 pub(crate) mod raw_dir_entry;
 pub(crate) mod raw_dir_entry_iterator;
 
 use dir_entry::{DirectoryEntry, DirectoryEntryRawInfo};
 
 use dir_entry_iterator::DirectoryEntryIterator;
+use entry_set::ExFatEntrySet;
+use raw_dir_entry::FatDirEntry;
 use raw_dir_entry_iterator::FatDirEntryIterator;
 
 use crate::utils::FileSystemIterator;
@@ -43,6 +51,9 @@ pub struct Directory<'a, S: StorageDevice> {
 pub struct File {
     /// The information about this file.
     pub file_info: DirectoryEntry,
+
+    /// The cached extents of the cluster chain of this file.
+    extent_cache: ExtentCache,
 }
 
 impl<'a, S: StorageDevice> Directory<'a, S> {
@@ -138,116 +149,268 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
         }
     }
 
-    /// Search space to allocate a directory entry and return a raw entry iterator to it.
-    fn allocate_entries(
-        entry: &DirectoryEntry,
+    /// Search space to allocate ``count`` contiguous raw directory entries and return a raw entry iterator to the first one.
+    pub(crate) fn allocate_entries(
+        entry: &mut DirectoryEntry,
         fs: &'a FatFileSystem<S>,
         count: u32,
     ) -> FatFileSystemResult<FatDirEntryIterator> {
-        let mut i = 0;
         let directory = Directory::from_entry(fs, *entry);
         let is_root_directory = directory.is_root_directory();
+        let is_exfat = fs.boot_record.fat_type == FatFsType::ExFat;
+
+        let mut first_free_entry = None;
+        let mut free_entry_count = 0;
 
         let mut fat_dir_entry_iter = directory.fat_dir_entry_iter();
         while let Some(raw_dir_entry) = fat_dir_entry_iter.next(fs) {
             let raw_dir_entry = raw_dir_entry?;
-            if raw_dir_entry.is_free() || raw_dir_entry.is_deleted() {
-                i += 1;
-                if i == count {
-                    return Ok(FatDirEntryIterator::new(
-                        fs,
-                        raw_dir_entry.entry_cluster,
-                        raw_dir_entry.entry_cluster_offset,
-                        raw_dir_entry.entry_offset,
-                        is_root_directory,
-                    ));
-                }
+
+            let is_available = if is_exfat {
+                !raw_dir_entry.is_exfat_in_use()
+            } else {
+                raw_dir_entry.is_free() || raw_dir_entry.is_deleted()
+            };
+
+            // The entries must be contiguous
+            if !is_available {
+                first_free_entry = None;
+                free_entry_count = 0;
+                continue;
             }
-        }
 
-        if is_root_directory {
-            match fs.boot_record.fat_type {
-                FatFsType::Fat12 | FatFsType::Fat16 => return Err(FatError::NoSpaceLeft),
-                _ => {}
+            if first_free_entry.is_none() {
+                first_free_entry = Some(raw_dir_entry);
             }
-        }
 
-        // if the directory is full, try to allocate a cluster and use it
-        let last_cluster = table::get_last_cluster(fs, entry.start_cluster)?;
-        let new_cluster = fs.alloc_cluster(Some(last_cluster))?;
+            free_entry_count += 1;
+            if free_entry_count == count {
+                break;
+            }
+        }
 
-        let clear_res = fs.clean_cluster_data(new_cluster);
+        if free_entry_count < count {
+            if is_root_directory {
+                match fs.boot_record.fat_type {
+                    FatFsType::Fat12 | FatFsType::Fat16 => return Err(FatError::NoSpaceLeft),
+                    _ => {}
+                }
+            }
 
-        if let Err(error) = clear_res {
-            // If it fail here, this can be catastrophic but at least we tried our best.
-            fs.free_cluster(new_cluster, Some(last_cluster))?;
-            return Err(error);
+            let new_cluster = Self::extend_directory(entry, fs, count - free_entry_count)?;
+            if first_free_entry.is_none() {
+                return Ok(FatDirEntryIterator::new(
+                    fs,
+                    new_cluster,
+                    0,
+                    0,
+                    is_root_directory,
+                    entry.no_fat_chain,
+                ));
+            }
         }
 
+        let first_free_entry = first_free_entry.unwrap();
+
         Ok(FatDirEntryIterator::new(
             fs,
-            new_cluster,
-            0,
-            0,
+            first_free_entry.entry_cluster,
+            first_free_entry.entry_cluster_offset,
+            first_free_entry.entry_offset,
             is_root_directory,
+            entry.no_fat_chain,
         ))
     }
 
+    /// Generate a 8.3 name for a given VFAT long name that isn't used in a given directory.
+    /// If ``current_short_name`` is provided, it is considered as available. (used when renaming an entry in place)
+    /// NOTE: The directory is scanned once to find the generated names already in use, a NoSpaceLeft error is returned if all of them are.
+    fn generate_short_name(
+        entry: &DirectoryEntry,
+        fs: &'a FatFileSystem<S>,
+        name: &str,
+        current_short_name: Option<&ShortFileName>,
+    ) -> FatFileSystemResult<ShortFileName> {
+        let mut context: ShortFileNameContext = ShortFileNameContext::default();
+
+        // One bit per generated name index, set when the name is used.
+        let mut used_indexes = [0x0u64; ShortFileNameGenerator::MAX_INDEX / 64];
+
+        let directory = Directory::from_entry(fs, *entry);
+        let mut fat_dir_entry_iter = directory.fat_dir_entry_iter();
+        while let Some(raw_dir_entry) = fat_dir_entry_iter.next(fs) {
+            let raw_dir_entry = raw_dir_entry?;
+
+            // End of directory
+            if raw_dir_entry.is_free() {
+                break;
+            }
+
+            if raw_dir_entry.is_deleted() {
+                continue;
+            }
+
+            let entry_short_name = match raw_dir_entry.short_name() {
+                Some(entry_short_name) => entry_short_name,
+                None => continue,
+            };
+
+            if let Some(current_short_name) = current_short_name {
+                if current_short_name.as_bytes() == entry_short_name.as_bytes() {
+                    continue;
+                }
+            }
+
+            // Only the first index, the tail value and the tail value with the checksum based base name can match.
+            let tail = entry_short_name.numeric_tail();
+            let candidates = [
+                Some(1),
+                tail,
+                tail.map(|tail| tail + ShortFileNameGenerator::BASE_NAME_INDEX_COUNT),
+            ];
+
+            for index in candidates.iter().filter_map(|index| *index) {
+                let is_used = ShortFileName::from_unformated_str(&mut context, name, index)
+                    .map(|short_name| short_name.as_bytes() == entry_short_name.as_bytes())
+                    .unwrap_or(false);
+
+                if is_used {
+                    used_indexes[(index - 1) / 64] |= 1 << ((index - 1) % 64);
+                }
+            }
+        }
+
+        for (position, used_bits) in used_indexes.iter().enumerate() {
+            if *used_bits != !0 {
+                let index = position * 64 + (!used_bits).trailing_zeros() as usize + 1;
+
+                return ShortFileName::from_unformated_str(&mut context, name, index)
+                    .ok_or(FatError::NoSpaceLeft);
+            }
+        }
+
+        Err(FatError::NoSpaceLeft)
+    }
+
+    /// Allocate enough clusters at the end of a directory to store ``count`` more raw entries and return the first new cluster.
+    fn extend_directory(
+        entry: &mut DirectoryEntry,
+        fs: &'a FatFileSystem<S>,
+        count: u32,
+    ) -> FatFileSystemResult<Cluster> {
+        let cluster_size = fs.boot_record.bytes_per_cluster();
+        let entries_per_cluster = cluster_size / FatDirEntry::LEN as u32;
+        let cluster_to_add_count = (count + entries_per_cluster - 1) / entries_per_cluster;
+
+        // exFAT directories without FAT chain need one before growing.
+        if entry.no_fat_chain {
+            table::link_contiguous_clusters(
+                fs,
+                entry.start_cluster,
+                (entry.file_size / u64::from(cluster_size)) as u32,
+            )?;
+            entry.no_fat_chain = false;
+        }
+
+        let mut last_cluster = table::get_last_cluster(fs, entry.start_cluster)?;
+        let mut first_new_cluster = None;
+
+        for _ in 0..cluster_to_add_count {
+            let new_cluster = fs.alloc_cluster(Some(last_cluster))?;
+
+            let clear_res = fs.clean_cluster_data(new_cluster);
+
+            if let Err(error) = clear_res {
+                // If it fail here, this can be catastrophic but at least we tried our best.
+                fs.free_cluster(new_cluster, Some(last_cluster))?;
+                return Err(error);
+            }
+
+            if first_new_cluster.is_none() {
+                first_new_cluster = Some(new_cluster);
+            }
+
+            last_cluster = new_cluster;
+        }
+
+        // exFAT directories (except the root directory) keep their size in their stream extension entry.
+        if fs.boot_record.fat_type == FatFsType::ExFat && entry.raw_info.is_some() {
+            entry.file_size += u64::from(cluster_to_add_count * cluster_size);
+            entry.valid_data_length = entry.file_size;
+            ExFatEntrySet::update_stream(fs, entry)?;
+        }
+
+        first_new_cluster.ok_or(FatError::NoSpaceLeft)
+    }
+
     /// Create a directory entry in a given parent directory.
     fn create_dir_entry(
         fs: &'a FatFileSystem<S>,
-        parent_entry: &DirectoryEntry,
+        parent_entry: &mut DirectoryEntry,
         attribute: Attributes,
         name: &str,
         cluster: Cluster,
-        file_size: u32,
+        file_size: u64,
     ) -> FatFileSystemResult<DirectoryEntry> {
-        let is_special_entry = name == "." || name == "..";
-        let mut count: u32 = 1;
-
-        let mut free_entries_iter = Self::allocate_entries(parent_entry, fs, count)?;
+        let now = fs.now();
+
+        if fs.boot_record.fat_type == FatFsType::ExFat {
+            let mut entry_set =
+                ExFatEntrySet::new(&fs.upcase_table, name, attribute, cluster, file_size)?;
+            entry_set.set_creation_datetime(&now);
+            entry_set.set_modification_datetime(&now);
+            entry_set.set_last_access_datetime(&now);
+            return Self::write_entry_set(fs, parent_entry, entry_set);
+        }
 
-        let mut first_raw_dir_entry = None;
+        let is_special_entry = name == "." || name == "..";
 
         let short_file_name;
+        let lfn_count;
         if !is_special_entry {
-            let mut context: ShortFileNameContext = ShortFileNameContext::default();
-            short_file_name = ShortFileName::from_unformated_str(&mut context, name);
+            short_file_name = Self::generate_short_name(parent_entry, fs, name, None)?;
+            lfn_count = LongFileName::entry_count(name);
+        } else {
+            short_file_name = ShortFileName::from_data(&name.as_bytes());
+            lfn_count = 0;
+        }
 
-            let lfn_count = (name.len() as u32 + 12) / 13;
-            let sfn_checksum = ShortFileName::checksum_lfn(&short_file_name.as_bytes());
+        let count = lfn_count + 1;
+        let mut free_entries_iter = Self::allocate_entries(parent_entry, fs, count)?;
 
-            for index in 0..lfn_count {
-                let target_index = lfn_count - index;
-                let lfn_index = if target_index == lfn_count {
-                    0x40u8 + target_index as u8
-                } else {
-                    target_index as u8
-                };
+        let mut first_raw_dir_entry = None;
 
-                let mut lfn_entry = free_entries_iter.next(fs).unwrap()?;
-                if first_raw_dir_entry.is_none() {
-                    first_raw_dir_entry = Some(lfn_entry);
-                }
+        let sfn_checksum = ShortFileName::checksum_lfn(&short_file_name.as_bytes());
 
-                lfn_entry.clear();
-                lfn_entry.set_attribute(Attributes::new(Attributes::LFN));
-                lfn_entry.set_lfn_index(lfn_index);
-                lfn_entry.set_lfn_entry(&name[(target_index - 1) as usize * 13..]);
-                lfn_entry.set_lfn_checksum(sfn_checksum as u8);
-                lfn_entry.flush(fs)?;
+        for index in 0..lfn_count {
+            let target_index = lfn_count - index;
+            let lfn_index = if target_index == lfn_count {
+                0x40u8 + target_index as u8
+            } else {
+                target_index as u8
+            };
+
+            let mut lfn_entry = free_entries_iter.next(fs).ok_or(FatError::NoSpaceLeft)??;
+            if first_raw_dir_entry.is_none() {
+                first_raw_dir_entry = Some(lfn_entry);
             }
 
-            count += lfn_count;
-        } else {
-            short_file_name = ShortFileName::from_data(&name.as_bytes());
+            lfn_entry.clear();
+            lfn_entry.set_attribute(Attributes::new(Attributes::LFN));
+            lfn_entry.set_lfn_index(lfn_index);
+            lfn_entry.set_lfn_entry(&LongFileName::from_utf8(name, (target_index - 1) as usize));
+            lfn_entry.set_lfn_checksum(sfn_checksum as u8);
+            lfn_entry.flush(fs)?;
         }
 
-        let mut sfn_entry = free_entries_iter.next(fs).unwrap()?;
+        let mut sfn_entry = free_entries_iter.next(fs).ok_or(FatError::NoSpaceLeft)??;
         sfn_entry.clear();
-        sfn_entry.set_file_size(file_size);
+        sfn_entry.set_file_size(file_size as u32);
         sfn_entry.set_cluster(cluster);
         sfn_entry.set_attribute(attribute);
+        sfn_entry.set_creation_datetime(&now);
+        sfn_entry.set_modification_datetime(&now);
+        sfn_entry.set_last_access_date(&now);
 
         sfn_entry.set_short_name(&short_file_name);
         sfn_entry.flush(fs)?;
@@ -260,11 +423,6 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
 
         let first_raw_dir_entry = first_raw_dir_entry.unwrap();
 
-        let is_in_old_root_directory = match fs.boot_record.fat_type {
-            FatFsType::Fat12 | FatFsType::Fat16 => cluster.0 == 0,
-            _ => false,
-        };
-
         Ok(DirectoryEntry::from_sfn(
             sfn_entry,
             Some(DirectoryEntryRawInfo::new(
@@ -272,12 +430,35 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
                 first_raw_dir_entry.entry_cluster_offset,
                 first_raw_dir_entry.entry_offset,
                 count,
-                is_in_old_root_directory,
+                free_entries_iter.cluster_iter.is_none(),
+                false,
             )),
             file_name,
         ))
     }
 
+    /// Write an exFAT entry set in a given parent directory.
+    fn write_entry_set(
+        fs: &'a FatFileSystem<S>,
+        parent_entry: &mut DirectoryEntry,
+        mut entry_set: ExFatEntrySet,
+    ) -> FatFileSystemResult<DirectoryEntry> {
+        let count = entry_set.len() as u32;
+        let mut free_entries_iter = Self::allocate_entries(parent_entry, fs, count)?;
+
+        entry_set.write(fs, &mut free_entries_iter)?;
+
+        let first_raw_dir_entry = entry_set.first_entry();
+        Ok(entry_set.to_directory_entry(Some(DirectoryEntryRawInfo::new(
+            first_raw_dir_entry.entry_cluster,
+            first_raw_dir_entry.entry_cluster_offset,
+            first_raw_dir_entry.entry_offset,
+            count,
+            false,
+            free_entries_iter.no_fat_chain,
+        ))))
+    }
+
     /// Delete a directory entry in a given parent directory.
     fn delete_dir_entry(
         fs: &'a FatFileSystem<S>,
@@ -290,14 +471,21 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
                 raw_info.first_entry_cluster_offset,
                 raw_info.first_entry_offset,
                 raw_info.in_old_fat_root_directory,
+                raw_info.parent_no_fat_chain,
             );
 
+            let is_exfat = fs.boot_record.fat_type == FatFsType::ExFat;
+
             let mut i = 0;
             while i < raw_info.entry_count {
                 if let Some(block_res) = offset_iter.next(fs) {
                     let mut res = block_res?;
 
-                    res.set_deleted();
+                    if is_exfat {
+                        res.set_exfat_deleted();
+                    } else {
+                        res.set_deleted();
+                    }
                     res.flush(fs)?;
                     i += 1;
                 } else {
@@ -311,7 +499,7 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
 
     /// Create a directory with the given name.
     pub fn create_directory(&mut self, name: &str) -> FatFileSystemResult<()> {
-        if name.len() > DirectoryEntry::MAX_FILE_NAME_LEN {
+        if name.encode_utf16().count() > DirectoryEntry::MAX_FILE_NAME_LEN {
             return Err(FatError::PathTooLong);
         }
 
@@ -326,13 +514,20 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
             return Err(error);
         }
 
+        // exFAT directories keep their size
+        let file_size = if self.fs.boot_record.fat_type == FatFsType::ExFat {
+            u64::from(self.fs.boot_record.bytes_per_cluster())
+        } else {
+            0
+        };
+
         let new_entry_res = Self::create_dir_entry(
             self.fs,
-            &self.dir_info,
+            &mut self.dir_info,
             Attributes::new(Attributes::DIRECTORY),
             name,
             cluster,
-            0,
+            file_size,
         );
 
         // Cannot create directory?
@@ -341,9 +536,14 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
             return Err(err);
         }
 
-        let entry = new_entry_res?;
+        let mut entry = new_entry_res?;
+
+        // exFAT directories don't have special entries
+        if self.fs.boot_record.fat_type == FatFsType::ExFat {
+            return Ok(());
+        }
 
-        let res = Self::create_special_directory_entries(self.fs, &entry);
+        let res = Self::create_special_directory_entries(self.fs, &mut entry);
 
         if let Err(err) = res {
             // If it fail here, this can be catastrophic but at least we tried our best.
@@ -358,14 +558,15 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
     /// Create directory special entries (".", "..")
     pub(crate) fn create_special_directory_entries(
         fs: &'a FatFileSystem<S>,
-        parent_entry: &DirectoryEntry,
+        parent_entry: &mut DirectoryEntry,
     ) -> FatFileSystemResult<()> {
+        let start_cluster = parent_entry.start_cluster;
         let res = Self::create_dir_entry(
             fs,
             parent_entry,
             Attributes::new(Attributes::DIRECTORY),
             ".",
-            parent_entry.start_cluster,
+            start_cluster,
             0,
         );
 
@@ -401,22 +602,23 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
         Ok(())
     }
 
-    /// Create a file with the given name.
-    pub fn create_file(&mut self, name: &str) -> FatFileSystemResult<()> {
-        if name.len() > DirectoryEntry::MAX_FILE_NAME_LEN {
+    /// Create a file with the given name and return it.
+    pub fn create_file(&mut self, name: &str) -> FatFileSystemResult<File> {
+        if name.encode_utf16().count() > DirectoryEntry::MAX_FILE_NAME_LEN {
             return Err(FatError::PathTooLong);
         }
 
-        Self::create_dir_entry(
+        // New files are marked as modified since their last backup.
+        let file_info = Self::create_dir_entry(
             self.fs,
-            &self.dir_info,
-            Attributes::new(0),
+            &mut self.dir_info,
+            Attributes::new(Attributes::ARCHIVE),
             name,
             Cluster(0),
             0,
         )?;
 
-        Ok(())
+        Ok(File::from_entry(file_info))
     }
 
     /// Delete a file at the given path.
@@ -443,23 +645,45 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
             return Err(FatError::NotAFile);
         }
 
-        // Check for directory not being empty
+        fs.check_modifiable(dir_entry.attribute)?;
+
+        // Check for directory not being empty (exFAT directories don't have special entries)
+        let special_entries_count = if fs.boot_record.fat_type == FatFsType::ExFat {
+            0
+        } else {
+            2
+        };
+
         if dir_entry.attribute.is_directory()
             && Self::from_entry(fs, dir_entry)
                 .iter()
-                .nth(self.fs, 2)
+                .nth(self.fs, special_entries_count)
                 .is_some()
         {
             return Err(FatError::AccessDenied);
         }
 
         Self::delete_dir_entry(fs, &dir_entry)?;
+        Self::free_entry_clusters(fs, &dir_entry)
+    }
 
-        if dir_entry.start_cluster.0 != 0 {
-            fs.free_cluster(dir_entry.start_cluster, None)?;
+    /// Free the clusters used by a directory entry.
+    fn free_entry_clusters(
+        fs: &'a FatFileSystem<S>,
+        dir_entry: &DirectoryEntry,
+    ) -> FatFileSystemResult<()> {
+        if dir_entry.start_cluster.0 == 0 {
+            return Ok(());
         }
 
-        Ok(())
+        if dir_entry.no_fat_chain {
+            let cluster_size = u64::from(fs.boot_record.bytes_per_cluster());
+            let cluster_count = utils::align_up(dir_entry.file_size, cluster_size) / cluster_size;
+
+            fs.free_contiguous_clusters(dir_entry.start_cluster, cluster_count as u32)
+        } else {
+            fs.free_cluster(dir_entry.start_cluster, None)
+        }
     }
 
     /// Rename a directory or a file from a given name to another one.
@@ -470,12 +694,24 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
         new_name: &str,
         is_dir: bool,
     ) -> FatFileSystemResult<()> {
-        if new_name.len() > DirectoryEntry::MAX_FILE_NAME_LEN {
+        if new_name.encode_utf16().count() > DirectoryEntry::MAX_FILE_NAME_LEN {
             return Err(FatError::PathTooLong);
         }
         let old_raw_info = dir_entry.raw_info.unwrap();
 
-        let new_entry_count = ((new_name.len() as u32 + 12) / 13) + 1;
+        // exFAT entry sets are always recreated with the new name, no special entries need to be updated.
+        if self.fs.boot_record.fat_type == FatFsType::ExFat {
+            let mut entry_set = ExFatEntrySet::read(self.fs, &old_raw_info)?;
+            entry_set.set_name(&self.fs.upcase_table, new_name)?;
+            entry_set.set_last_access_datetime(&self.fs.now());
+
+            let mut dir_info = self.dir_info;
+            Self::write_entry_set(self.fs, &mut dir_info, entry_set)?;
+
+            return Self::delete_dir_entry(self.fs, &dir_entry);
+        }
+
+        let new_entry_count = LongFileName::entry_count(new_name) + 1;
 
         // can we update in place?
         if old_raw_info.entry_count == new_entry_count
@@ -487,12 +723,18 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
                 old_raw_info.first_entry_cluster_offset,
                 old_raw_info.first_entry_offset,
                 old_raw_info.in_old_fat_root_directory,
+                old_raw_info.parent_no_fat_chain,
             );
 
-            let mut context: ShortFileNameContext = ShortFileNameContext::default();
-            let short_file_name = ShortFileName::from_unformated_str(&mut context, new_name);
+            let old_short_name = old_raw_info.get_dir_entry(self.fs)?.short_name();
+            let short_file_name = Self::generate_short_name(
+                &self.dir_info,
+                self.fs,
+                new_name,
+                old_short_name.as_ref(),
+            )?;
 
-            let lfn_count = (new_name.len() as u32 + 12) / 13;
+            let lfn_count = LongFileName::entry_count(new_name);
             let sfn_checksum = ShortFileName::checksum_lfn(&short_file_name.as_bytes());
 
             for index in 0..lfn_count {
@@ -508,26 +750,39 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
                 lfn_entry.clear();
                 lfn_entry.set_attribute(Attributes::new(Attributes::LFN));
                 lfn_entry.set_lfn_index(lfn_index);
-                lfn_entry.set_lfn_entry(&new_name[(target_index - 1) as usize * 13..]);
+                lfn_entry.set_lfn_entry(&LongFileName::from_utf8(
+                    new_name,
+                    (target_index - 1) as usize,
+                ));
                 lfn_entry.set_lfn_checksum(sfn_checksum as u8);
                 lfn_entry.flush(self.fs)?;
             }
 
             let mut sfn_entry = entries_iter.next(self.fs).unwrap()?;
             sfn_entry.set_short_name(&short_file_name);
+            sfn_entry.set_last_access_date(&self.fs.now());
             sfn_entry.flush(self.fs)?;
             return Ok(());
         }
 
+        let old_sfn_entry = old_raw_info.get_dir_entry(self.fs)?;
+
+        let mut dir_info = self.dir_info;
         let new_entry = Self::create_dir_entry(
             self.fs,
-            &self.dir_info,
+            &mut dir_info,
             dir_entry.attribute,
             new_name,
             dir_entry.start_cluster,
             dir_entry.file_size,
         )?;
 
+        // Keep the creation and modification datetimes of the old entry.
+        let mut new_sfn_entry = new_entry.raw_info.unwrap().get_dir_entry(self.fs)?;
+        new_sfn_entry.set_creation_datetime(&old_sfn_entry.get_creation_datetime());
+        new_sfn_entry.set_modification_datetime(&old_sfn_entry.get_modification_datetime());
+        new_sfn_entry.flush(self.fs)?;
+
         if is_dir {
             let new_raw_info = new_entry.raw_info.unwrap();
 
@@ -555,6 +810,7 @@ impl<'a, S: StorageDevice> Directory<'a, S> {
                     0,
                     0,
                     new_raw_info.in_old_fat_root_directory,
+                    false,
                 );
 
                 // FIXME: is that always the second entry?
@@ -589,9 +845,19 @@ impl FatDirEntryIterator {
         let cluster_iter = if root.is_root_directory() {
             match fs.boot_record.fat_type {
                 FatFsType::Fat12 | FatFsType::Fat16 => None,
-                FatFsType::Fat32 => Some(ClusterOffsetIter::new(fs, cluster, None)),
-                _ => unimplemented!(),
+                FatFsType::Fat32 | FatFsType::ExFat => {
+                    Some(ClusterOffsetIter::new(fs, cluster, None))
+                }
             }
+        } else if root.dir_info.no_fat_chain {
+            let cluster_count =
+                root.dir_info.file_size / u64::from(fs.boot_record.bytes_per_cluster());
+
+            Some(ClusterOffsetIter::from_cluster_iter(
+                fs,
+                FatClusterIter::new_contiguous(cluster, cluster_count as u32),
+                None,
+            ))
         } else {
             Some(ClusterOffsetIter::new(fs, cluster, None))
         };
@@ -602,6 +868,7 @@ impl FatDirEntryIterator {
             is_first: true,
             cluster_iter,
             last_cluster: None,
+            no_fat_chain: root.dir_info.no_fat_chain,
         }
     }
 }
@@ -618,7 +885,10 @@ impl DirectoryEntryIterator {
 impl File {
     /// Create a file from a filesystem reference and a directory entry.
     pub fn from_entry(file_info: DirectoryEntry) -> Self {
-        File { file_info }
+        File {
+            file_info,
+            extent_cache: ExtentCache::new(),
+        }
     }
 
     /// Check offset range for a given fat_type.
@@ -627,7 +897,7 @@ impl File {
             FatFsType::Fat12 => 0x01FF_FFFF,
             FatFsType::Fat16 => 0x7FFF_FFFF,
             FatFsType::Fat32 => 0xFFFF_FFFF,
-            _ => unimplemented!(),
+            FatFsType::ExFat => core::u64::MAX,
         };
 
         if offset > max_size {
@@ -637,6 +907,88 @@ impl File {
         Ok(())
     }
 
+    /// Create a cluster iterator over the file.
+    fn cluster_iter<S: StorageDevice>(&self, fs: &FatFileSystem<S>) -> FatClusterIter {
+        if self.file_info.no_fat_chain {
+            let cluster_size = u64::from(fs.boot_record.bytes_per_cluster());
+            let cluster_count =
+                utils::align_up(self.file_info.file_size, cluster_size) / cluster_size;
+
+            FatClusterIter::new_contiguous(self.file_info.start_cluster, cluster_count as u32)
+        } else {
+            FatClusterIter::new(fs, self.file_info.start_cluster)
+        }
+    }
+
+    /// Load the extents of the cluster chain of the file if they aren't cached yet or are stale.
+    fn load_extents<S: StorageDevice>(&mut self, fs: &FatFileSystem<S>) {
+        if self.extent_cache.is_valid(fs) {
+            return;
+        }
+
+        let cluster_size = u64::from(fs.boot_record.bytes_per_cluster());
+        let cluster_count = utils::align_up(self.file_info.file_size, cluster_size) / cluster_size;
+
+        // Empty files don't have any cluster chain to follow.
+        let cluster_iter = if self.file_info.start_cluster.0 == 0 || cluster_count == 0 {
+            FatClusterIter::new_contiguous(self.file_info.start_cluster, 0)
+        } else {
+            self.cluster_iter(fs)
+        };
+
+        self.extent_cache
+            .load(fs, cluster_iter, cluster_count as u32);
+    }
+
+    /// Split the range of ``len`` bytes of the file at ``offset`` into physically contiguous runs of clusters.
+    /// ``transfer`` is called with the offset on the storage device and the range in the buffer of every run.
+    /// Return the count of bytes covered by the cluster chain.
+    fn for_each_run<S: StorageDevice, F>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        offset: u64,
+        len: u64,
+        mut transfer: F,
+    ) -> FatFileSystemResult<u64>
+    where
+        F: FnMut(u64, Range<usize>) -> FatFileSystemResult<()>,
+    {
+        let cluster_size = u64::from(fs.boot_record.bytes_per_cluster());
+        self.load_extents(fs);
+
+        let mut done = 0u64;
+
+        while done < len {
+            let position = offset + done;
+            let cluster_offset = position % cluster_size;
+            let max_cluster_count =
+                utils::align_up(cluster_offset + len - done, cluster_size) / cluster_size;
+
+            let (cluster, cluster_count) = match self.extent_cache.get_run(
+                fs,
+                (position / cluster_size) as u32,
+                max_cluster_count as u32,
+            ) {
+                Some(run) => run,
+                None => break,
+            };
+
+            let run_len = core::cmp::min(
+                u64::from(cluster_count) * cluster_size - cluster_offset,
+                len - done,
+            );
+
+            transfer(
+                fs.partition_start + cluster.to_data_bytes_offset(fs) + cluster_offset,
+                done as usize..(done + run_len) as usize,
+            )?;
+
+            done += run_len;
+        }
+
+        Ok(done)
+    }
+
     /// Read at a given offset of the file into a given buffer.
     pub fn read<S: StorageDevice>(
         &mut self,
@@ -648,61 +1000,44 @@ impl File {
             return Ok(0);
         }
 
-        if offset >= u64::from(self.file_info.file_size) {
+        let file_size = self.file_info.file_size;
+
+        if offset >= file_size {
             return Ok(0);
         }
 
-        let blocks_per_cluster = u64::from(fs.boot_record.blocks_per_cluster());
-        let block_size = u64::from(fs.boot_record.bytes_per_block());
-        let mut cluster_offset_iterator =
-            ClusterOffsetIter::new(fs, self.file_info.start_cluster, Some(offset / block_size));
-
-        let mut read_size = 0u64;
-
-        while read_size < buf.len() as u64 {
-            let cluster_opt = cluster_offset_iterator.next(fs);
-            if cluster_opt.is_none() {
-                break;
-            }
-
-            let cluster = cluster_opt.unwrap();
-
-            let mut buf_limit = block_size;
+        let buf_len = core::cmp::min(buf.len() as u64, file_size - offset);
+        let buf = &mut buf[..buf_len as usize];
 
-            let bytes_left = u64::from(self.file_info.file_size) - read_size - offset;
-
-            if bytes_left == 0 {
-                break;
-            } else if bytes_left < buf_limit {
-                buf_limit = bytes_left;
-            }
-
-            if buf_limit > buf.len() as u64 {
-                buf_limit = buf.len() as u64;
-            }
-
-            let mut buf_slice = &mut buf[read_size as usize..(read_size + buf_limit) as usize];
+        // On exFAT, the data after the valid data length must be read as zeros.
+        let valid_data_length = core::cmp::min(self.file_info.valid_data_length, file_size);
+        let read_len = if offset >= valid_data_length {
+            0
+        } else {
+            core::cmp::min(buf_len, valid_data_length - offset)
+        };
 
-            let cluster_offset = cluster.to_data_bytes_offset(fs);
+        for value in buf[read_len as usize..].iter_mut() {
+            *value = 0;
+        }
 
+        let read_size = self.for_each_run(fs, offset, read_len, |storage_offset, range| {
             fs.storage_device
                 .lock()
-                .read(
-                    fs.partition_start
-                        + cluster_offset
-                        + (offset % block_size)
-                        + (read_size % (block_size * blocks_per_cluster)),
-                    &mut buf_slice,
-                )
-                .or(Err(FatError::ReadFailed))?;
+                .read(storage_offset, &mut buf[range])
+                .or(Err(FatError::ReadFailed))
+        })?;
 
-            read_size += buf_slice.len() as u64;
+        // The cluster chain is shorter than expected
+        if read_size < read_len {
+            return Ok(read_size);
         }
 
-        Ok(read_size)
+        Ok(buf_len)
     }
 
     /// Write the given buffer at a given offset of the file.
+    /// NOTE: The read only attribute is checked as it was when the file was opened or last resized.
     pub fn write<S: StorageDevice>(
         &mut self,
         fs: &FatFileSystem<S>,
@@ -712,51 +1047,135 @@ impl File {
     ) -> FatFileSystemResult<()> {
         Self::check_range(offset, fs.boot_record.fat_type)?;
 
-        let min_size = offset + buf.len() as u64;
-        if min_size > u64::from(self.file_info.file_size) {
+        // Nothing is modified, the file isn't touched.
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        fs.check_modifiable(self.file_info.attribute)?;
+
+        // The end of the write must be representable.
+        let min_size = offset
+            .checked_add(buf.len() as u64)
+            .ok_or(FatError::AccessDenied)?;
+        if min_size > self.file_info.file_size {
             if appendable {
-                self.set_len(fs, min_size)?;
+                self.resize(fs, min_size)?;
             } else {
                 return Err(FatError::AccessDenied);
             }
         }
 
-        let blocks_per_cluster = u64::from(fs.boot_record.blocks_per_cluster());
-        let block_size = u64::from(fs.boot_record.bytes_per_block());
-        let mut cluster_offset_iterator =
-            ClusterOffsetIter::new(fs, self.file_info.start_cluster, Some(offset / block_size));
+        let valid_data_length = self.file_info.valid_data_length;
 
-        let mut write_size = 0u64;
+        // On exFAT, the data between the valid data length and the write must be zeroed.
+        if offset > valid_data_length {
+            self.zero_data(fs, valid_data_length, offset)?;
+        }
 
-        while write_size < buf.len() as u64 {
-            let cluster_opt = cluster_offset_iterator.next(fs);
-            if cluster_opt.is_none() {
-                break;
+        self.write_data(fs, offset, buf)?;
+
+        if min_size > valid_data_length {
+            self.file_info.valid_data_length = min_size;
+
+            if fs.boot_record.fat_type == FatFsType::ExFat {
+                ExFatEntrySet::update_stream(fs, &self.file_info)?;
             }
+        }
 
-            let cluster = cluster_opt.unwrap();
+        self.touch(fs)
+    }
+
+    /// Read the attribute of the file from its directory entry.
+    /// NOTE: The attribute may have been changed since the file was opened, the cached one is updated.
+    fn read_attribute<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+    ) -> FatFileSystemResult<Attributes> {
+        let raw_file_info = self.file_info.raw_info.ok_or(FatError::Custom {
+            name: "Raw Info is missing ON A FILE",
+        })?;
 
-            let mut buf_limit = block_size;
+        let attribute = if fs.boot_record.fat_type == FatFsType::ExFat {
+            ExFatEntrySet::read(fs, &raw_file_info)?.attribute()
+        } else {
+            raw_file_info.get_dir_entry(fs)?.attribute()
+        };
 
-            if buf_limit > (&buf[write_size as usize..]).len() as u64 {
-                buf_limit = (&buf[write_size as usize..]).len() as u64;
-            }
+        self.file_info.attribute = attribute;
 
-            let buf_slice = &buf[write_size as usize..(write_size + buf_limit) as usize];
+        Ok(attribute)
+    }
+
+    /// Set the last modification and last access datetimes of the file to the current datetime.
+    /// The archive attribute is also set to mark the file as modified since its last backup.
+    fn touch<S: StorageDevice>(&mut self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        let raw_file_info = self.file_info.raw_info.ok_or(FatError::Custom {
+            name: "Raw Info is missing ON A FILE",
+        })?;
 
-            let cluster_offset = cluster.to_data_bytes_offset(fs);
+        let now = fs.now();
+
+        let attribute = if fs.boot_record.fat_type == FatFsType::ExFat {
+            let mut entry_set = ExFatEntrySet::read(fs, &raw_file_info)?;
+            let attribute =
+                Attributes::new(entry_set.attribute().get_value() | Attributes::ARCHIVE);
+            entry_set.set_attribute(attribute);
+            entry_set.set_modification_datetime(&now);
+            entry_set.set_last_access_datetime(&now);
+            entry_set.flush(fs)?;
+            attribute
+        } else {
+            let mut raw_dir_entry = raw_file_info.get_dir_entry(fs)?;
+            let attribute =
+                Attributes::new(raw_dir_entry.attribute().get_value() | Attributes::ARCHIVE);
+            raw_dir_entry.set_attribute(attribute);
+            raw_dir_entry.set_modification_datetime(&now);
+            raw_dir_entry.set_last_access_date(&now);
+            raw_dir_entry.flush(fs)?;
+            attribute
+        };
+
+        self.file_info.attribute = attribute;
+        self.file_info.last_modification_timestamp = now.to_unix_time();
+        self.file_info.last_access_timestamp = now.to_unix_time();
 
+        Ok(())
+    }
+
+    /// Write the given buffer at a given offset of the file without any size check.
+    fn write_data<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        offset: u64,
+        buf: &[u8],
+    ) -> FatFileSystemResult<()> {
+        fs.mark_dirty()?;
+
+        self.for_each_run(fs, offset, buf.len() as u64, |storage_offset, range| {
             fs.storage_device
                 .lock()
-                .write(
-                    fs.partition_start
-                        + cluster_offset
-                        + (offset % block_size)
-                        + (write_size % (block_size * blocks_per_cluster)),
-                    &buf_slice,
-                )
-                .or(Err(FatError::WriteFailed))?;
-            write_size += buf_slice.len() as u64;
+                .write(storage_offset, &buf[range])
+                .or(Err(FatError::WriteFailed))
+        })?;
+
+        Ok(())
+    }
+
+    /// Zero the data of the file in the ``[start, end)`` range without any size check.
+    fn zero_data<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        start: u64,
+        end: u64,
+    ) -> FatFileSystemResult<()> {
+        let zeros = [0x0u8; crate::MINIMAL_BLOCK_SIZE];
+        let mut position = start;
+
+        while position < end {
+            let size = core::cmp::min(zeros.len() as u64, end - position);
+            self.write_data(fs, position, &zeros[..size as usize])?;
+            position += size;
         }
 
         Ok(())
@@ -768,72 +1187,221 @@ impl File {
         fs: &FatFileSystem<S>,
         size: u64,
     ) -> FatFileSystemResult<()> {
-        let current_len = u64::from(self.file_info.file_size);
-        if size == current_len {
+        if size == self.file_info.file_size {
             return Ok(());
-        } else if size > 0xFFFF_FFFF {
+        }
+
+        fs.check_modifiable(self.read_attribute(fs)?)?;
+
+        self.resize(fs, size)?;
+        self.touch(fs)
+    }
+
+    /// Resize the cluster chain of the file and update its size without touching the file.
+    fn resize<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        size: u64,
+    ) -> FatFileSystemResult<()> {
+        let is_exfat = fs.boot_record.fat_type == FatFsType::ExFat;
+
+        let current_len = self.file_info.file_size;
+        if !is_exfat && size > 0xFFFF_FFFF {
             return Err(FatError::NoSpaceLeft);
         }
 
-        let raw_file_info = self.file_info.raw_info.ok_or(FatError::Custom {
+        self.file_info.raw_info.ok_or(FatError::Custom {
             name: "Raw Info is missing ON A FILE",
         })?;
-        let mut raw_dir_entry = raw_file_info.get_dir_entry(fs)?;
 
-        let cluster_size = u64::from(
-            u16::from(fs.boot_record.blocks_per_cluster()) * fs.boot_record.bytes_per_block(),
-        );
+        let cluster_size = u64::from(fs.boot_record.bytes_per_cluster());
         let aligned_size = utils::align_up(size, cluster_size);
         let aligned_current_len = utils::align_up(current_len, cluster_size);
 
-        let new_size;
-
         if size > current_len {
-            let diff_size = size - current_len;
             let mut cluster_to_add_count = (aligned_size - aligned_current_len) / cluster_size;
-            let mut start_cluster =
-                if self.file_info.start_cluster.0 == 0 || self.file_info.file_size == 0 {
-                    None
-                } else {
-                    Some(table::get_last_cluster(fs, self.file_info.start_cluster)?)
-                };
+
+            // exFAT files without FAT chain need one before growing.
+            if self.file_info.no_fat_chain && cluster_to_add_count != 0 {
+                if self.file_info.start_cluster.0 != 0 && current_len != 0 {
+                    table::link_contiguous_clusters(
+                        fs,
+                        self.file_info.start_cluster,
+                        (aligned_current_len / cluster_size) as u32,
+                    )?;
+                }
+                self.file_info.no_fat_chain = false;
+            }
+
+            self.load_extents(fs);
+
+            let mut start_cluster = if self.file_info.start_cluster.0 == 0 || current_len == 0 {
+                None
+            } else {
+                self.extent_cache
+                    .get_last_cluster(fs, !self.file_info.no_fat_chain)?
+            };
 
             let mut last_cluster = start_cluster;
             let need_update_cluster = start_cluster.is_none();
 
             while cluster_to_add_count != 0 {
                 last_cluster = Some(fs.alloc_cluster(last_cluster)?);
+                self.extent_cache.push(last_cluster.unwrap());
                 if start_cluster.is_none() {
                     start_cluster = last_cluster;
                 }
                 cluster_to_add_count -= 1;
             }
 
-            new_size = self.file_info.file_size + diff_size as u32;
             if need_update_cluster {
                 self.file_info.start_cluster = start_cluster.unwrap();
             }
         } else {
-            let diff_size = current_len - size;
             let mut cluster_to_remove_count = (aligned_current_len - aligned_size) / cluster_size;
 
-            while cluster_to_remove_count != 0 {
-                let (last_cluster, previous_cluster) =
-                    table::get_last_and_previous_cluster(fs, self.file_info.start_cluster)?;
-                fs.free_cluster(last_cluster, previous_cluster)?;
-                cluster_to_remove_count -= 1;
+            if self.file_info.no_fat_chain {
+                fs.free_contiguous_clusters(
+                    Cluster(self.file_info.start_cluster.0 + (aligned_size / cluster_size) as u32),
+                    cluster_to_remove_count as u32,
+                )?;
+            } else {
+                while cluster_to_remove_count != 0 {
+                    let (last_cluster, previous_cluster) =
+                        table::get_last_and_previous_cluster(fs, self.file_info.start_cluster)?;
+                    fs.free_cluster(last_cluster, previous_cluster)?;
+                    cluster_to_remove_count -= 1;
+                }
+            }
+
+            if size == 0 {
+                self.file_info.start_cluster = Cluster(0);
+                self.file_info.no_fat_chain = false;
             }
 
-            new_size = self.file_info.file_size - diff_size as u32;
+            self.extent_cache.invalidate();
         }
-        // TODO: update modified date?
-        // BODY: We should update the modification date here at some point.
-        raw_dir_entry.set_cluster(self.file_info.start_cluster);
-        raw_dir_entry.set_file_size(new_size);
-        raw_dir_entry.flush(fs)?;
 
-        self.file_info.file_size = new_size;
+        self.update_len(fs, size)
+    }
 
-        Ok(())
+    /// Grow the file to ``size`` bytes, allocating the missing clusters as one physically contiguous run.
+    /// The run is placed right after the last cluster of the file when possible, preallocating an empty file thus gives a contiguous file.
+    /// Return a NoSpaceLeft error if no free run of clusters is large enough, the size of the file is left unchanged in this case.
+    /// NOTE: This does nothing if the file is already larger than ``size``.
+    /// NOTE: On exFAT, no FAT chain is written while the file stays contiguous.
+    /// NOTE: The new content of the file reads as zeros, on FAT12/FAT16/FAT32 it is zeroed on the storage device.
+    pub fn preallocate<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        size: u64,
+    ) -> FatFileSystemResult<()> {
+        let current_len = self.file_info.file_size;
+        if size <= current_len {
+            return Ok(());
+        } else if fs.boot_record.fat_type != FatFsType::ExFat && size > 0xFFFF_FFFF {
+            return Err(FatError::NoSpaceLeft);
+        }
+
+        fs.check_modifiable(self.read_attribute(fs)?)?;
+
+        self.file_info.raw_info.ok_or(FatError::Custom {
+            name: "Raw Info is missing ON A FILE",
+        })?;
+
+        let cluster_size = u64::from(fs.boot_record.bytes_per_cluster());
+        let aligned_current_len = utils::align_up(current_len, cluster_size);
+        let cluster_to_add_count =
+            (utils::align_up(size, cluster_size) - aligned_current_len) / cluster_size;
+
+        if cluster_to_add_count != 0 {
+            if cluster_to_add_count > u64::from(fs.boot_record.cluster_count) {
+                return Err(FatError::NoSpaceLeft);
+            }
+
+            self.load_extents(fs);
+
+            let last_cluster = if self.file_info.start_cluster.0 == 0 || current_len == 0 {
+                None
+            } else {
+                self.extent_cache
+                    .get_last_cluster(fs, !self.file_info.no_fat_chain)?
+            };
+
+            // On exFAT, no FAT chain is needed when the file stays contiguous.
+            let no_fat_chain = fs.boot_record.fat_type == FatFsType::ExFat
+                && match last_cluster {
+                    None => true,
+                    Some(last_cluster) => {
+                        self.file_info.no_fat_chain
+                            && fs.is_free_cluster_run(
+                                Cluster(last_cluster.0 + 1),
+                                cluster_to_add_count as u32,
+                            )?
+                    }
+                };
+
+            // exFAT files without FAT chain need one before growing elsewhere.
+            if self.file_info.no_fat_chain && !no_fat_chain && last_cluster.is_some() {
+                table::link_contiguous_clusters(
+                    fs,
+                    self.file_info.start_cluster,
+                    (aligned_current_len / cluster_size) as u32,
+                )?;
+            }
+
+            let first_cluster = fs.alloc_contiguous_clusters(
+                last_cluster,
+                cluster_to_add_count as u32,
+                no_fat_chain,
+            )?;
+            self.file_info.no_fat_chain = no_fat_chain;
+
+            for index in 0..cluster_to_add_count as u32 {
+                self.extent_cache.push(Cluster(first_cluster.0 + index));
+            }
+
+            if last_cluster.is_none() {
+                self.file_info.start_cluster = first_cluster;
+            }
+        }
+
+        // On FAT12/FAT16/FAT32, the end of the last cluster becomes part of the file and must be zeroed.
+        // NOTE: The new clusters are zeroed when allocated.
+        if fs.boot_record.fat_type != FatFsType::ExFat && current_len < aligned_current_len {
+            self.zero_data(fs, current_len, core::cmp::min(aligned_current_len, size))?;
+        }
+
+        self.update_len(fs, size)?;
+        self.touch(fs)
+    }
+
+    /// Update the size of the file in its directory entry after its cluster chain was resized.
+    fn update_len<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        size: u64,
+    ) -> FatFileSystemResult<()> {
+        let raw_file_info = self.file_info.raw_info.ok_or(FatError::Custom {
+            name: "Raw Info is missing ON A FILE",
+        })?;
+
+        self.file_info.file_size = size;
+
+        if fs.boot_record.fat_type == FatFsType::ExFat {
+            if self.file_info.valid_data_length > size {
+                self.file_info.valid_data_length = size;
+            }
+
+            return ExFatEntrySet::update_stream(fs, &self.file_info);
+        }
+
+        // The whole file is always valid on FAT12/FAT16/FAT32.
+        self.file_info.valid_data_length = size;
+
+        let mut raw_dir_entry = raw_file_info.get_dir_entry(fs)?;
+        raw_dir_entry.set_cluster(self.file_info.start_cluster);
+        raw_dir_entry.set_file_size(size as u32);
+        raw_dir_entry.flush(fs)
     }
 }
@@ -1,6 +1,6 @@
 //! Low level directory entry representation.
 use byteorder::{ByteOrder, LittleEndian};
-use structview::{u16_le, u32_le, View};
+use structview::{u16_le, u32_le, u64_le, View};
 
 use crate::attribute::Attributes;
 use crate::cluster::Cluster;
@@ -86,8 +86,130 @@ pub struct ShortFileNameDirEntry {
     pub file_size: u32_le,
 }
 
+/// Represent an exFAT file directory entry.
+#[derive(Clone, Copy, View)]
+#[repr(C)]
+pub struct ExFatFileDirEntry {
+    /// The entry type (always 0x85).
+    pub entry_type: u8,
+
+    /// The count of secondary entries following this entry.
+    pub secondary_count: u8,
+
+    /// The checksum of the entry set.
+    pub set_checksum: u16_le,
+
+    /// File Attributes.
+    pub file_attributes: u16_le,
+
+    /// Reserved.
+    pub reserved1: u16_le,
+
+    /// The date and time on which the file was created.
+    pub create_timestamp: u32_le,
+
+    /// The date and time on which the file was last modified.
+    pub last_modified_timestamp: u32_le,
+
+    /// The date and time on which the file was last accessed.
+    pub last_accessed_timestamp: u32_le,
+
+    /// Create time fine resolution (10 ms units, values from 0 to 199).
+    pub create_10ms_increment: u8,
+
+    /// Last modified time fine resolution (10 ms units, values from 0 to 199).
+    pub last_modified_10ms_increment: u8,
+
+    /// The creation UTC offset.
+    pub create_utc_offset: u8,
+
+    /// The last modification UTC offset.
+    pub last_modified_utc_offset: u8,
+
+    /// The last access UTC offset.
+    pub last_accessed_utc_offset: u8,
+
+    /// Reserved.
+    pub reserved2: [u8; 7],
+}
+
+/// Represent an exFAT stream extension directory entry.
+#[derive(Clone, Copy, View)]
+#[repr(C)]
+pub struct ExFatStreamExtensionDirEntry {
+    /// The entry type (always 0xC0).
+    pub entry_type: u8,
+
+    /// The secondary flags (allocation possible, no FAT chain).
+    pub general_secondary_flags: u8,
+
+    /// Reserved.
+    pub reserved1: u8,
+
+    /// The length of the file name in UTF-16 characters.
+    pub name_length: u8,
+
+    /// The hash of the up-cased file name.
+    pub name_hash: u16_le,
+
+    /// Reserved.
+    pub reserved2: u16_le,
+
+    /// The size of the data actually written in the file.
+    pub valid_data_length: u64_le,
+
+    /// Reserved.
+    pub reserved3: u32_le,
+
+    /// The first cluster of the file.
+    pub first_cluster: u32_le,
+
+    /// The size of the file in bytes.
+    pub data_length: u64_le,
+}
+
+/// Represent an exFAT file name directory entry.
+#[derive(Clone, Copy, View)]
+#[repr(C)]
+pub struct ExFatFileNameDirEntry {
+    /// The entry type (always 0xC1).
+    pub entry_type: u8,
+
+    /// The secondary flags (always 0x00).
+    pub general_secondary_flags: u8,
+
+    /// Name characters. (fifteen UTF-16 characters)
+    pub file_name: [u16_le; 15],
+}
+
+/// Represent an exFAT allocation bitmap or up-case table directory entry.
+#[derive(Clone, Copy, View)]
+#[repr(C)]
+pub struct ExFatMetadataDirEntry {
+    /// The entry type (0x81 for the allocation bitmap, 0x82 for the up-case table).
+    pub entry_type: u8,
+
+    /// Bitmap flags on allocation bitmap entries.
+    pub flags: u8,
+
+    /// Reserved.
+    pub reserved1: [u8; 2],
+
+    /// The checksum of the up-case table.
+    pub table_checksum: u32_le,
+
+    /// Reserved.
+    pub reserved2: [u8; 12],
+
+    /// The first cluster of the data.
+    pub first_cluster: u32_le,
+
+    /// The size of the data in bytes.
+    pub data_length: u64_le,
+}
+
 #[derive(Clone, Copy)]
-/// Represent a FAT directory entry (8.3 entry/VFAT long entry)
+/// Represent a FAT directory entry (8.3 entry/VFAT long entry/exFAT entry)
 pub struct FatDirEntry {
     /// The cluster where this entry is.
     pub entry_cluster: Cluster,
@@ -106,6 +228,33 @@ impl FatDirEntry {
     /// The length of a FAT directory entry.
     pub const LEN: usize = 32;
 
+    /// The exFAT allocation bitmap entry type.
+    pub const EXFAT_ALLOCATION_BITMAP: u8 = 0x81;
+
+    /// The exFAT up-case table entry type.
+    pub const EXFAT_UPCASE_TABLE: u8 = 0x82;
+
+    /// The exFAT volume label entry type.
+    pub const EXFAT_VOLUME_LABEL: u8 = 0x83;
+
+    /// The exFAT file entry type.
+    pub const EXFAT_FILE: u8 = 0x85;
+
+    /// The exFAT stream extension entry type.
+    pub const EXFAT_STREAM_EXTENSION: u8 = 0xC0;
+
+    /// The exFAT file name entry type.
+    pub const EXFAT_FILE_NAME: u8 = 0xC1;
+
+    /// The exFAT "in use" bit of the entry type.
+    const EXFAT_IN_USE: u8 = 0x80;
+
+    /// The exFAT "allocation possible" secondary flag.
+    pub const EXFAT_ALLOCATION_POSSIBLE: u8 = 0x1;
+
+    /// The exFAT "no FAT chain" secondary flag.
+    pub const EXFAT_NO_FAT_CHAIN: u8 = 0x2;
+
     /// Create a new FAT directory entry representation from raw data.
     pub fn from_raw(
         data: &[u8],
@@ -144,6 +293,16 @@ impl FatDirEntry {
         self.data[0] = 0xE5;
     }
 
+    /// Check if the "in use" bit is set on an exFAT entry.
+    pub fn is_exfat_in_use(&self) -> bool {
+        (self.get_first_byte() & Self::EXFAT_IN_USE) != 0
+    }
+
+    /// Clear the "in use" bit of an exFAT entry.
+    pub fn set_exfat_deleted(&mut self) {
+        self.data[0] &= !Self::EXFAT_IN_USE;
+    }
+
     /// Reint the raw data buffer to a free state.
     pub fn clear(&mut self) {
         self.data = [0x0u8; Self::LEN];
@@ -151,6 +310,8 @@ impl FatDirEntry {
 
     /// Write the raw data buffer to disk.
     pub fn flush<S: StorageDevice>(&self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        fs.mark_dirty()?;
+
         let is_in_old_root_directory = match fs.boot_record.fat_type {
             FatFsType::Fat12 | FatFsType::Fat16 => self.entry_cluster.0 == 0,
             _ => false,
@@ -225,9 +386,7 @@ impl FatDirEntry {
     }
 
     /// Set the LFN in the VFAT long entry.
-    pub fn set_lfn_entry(&mut self, lfn: &str) {
-        let lfn = LongFileName::from_utf8(lfn);
-
+    pub fn set_lfn_entry(&mut self, lfn: &LongFileName) {
         let lfn = lfn.as_contents();
 
         for (i, entry) in lfn.iter().enumerate().take(5) {
@@ -259,6 +418,26 @@ impl FatDirEntry {
         ShortFileNameDirEntry::view(&self.data).unwrap()
     }
 
+    /// Read the raw data as an exFAT file entry.
+    pub fn as_exfat_file_entry(&self) -> &ExFatFileDirEntry {
+        ExFatFileDirEntry::view(&self.data).unwrap()
+    }
+
+    /// Read the raw data as an exFAT stream extension entry.
+    pub fn as_exfat_stream_entry(&self) -> &ExFatStreamExtensionDirEntry {
+        ExFatStreamExtensionDirEntry::view(&self.data).unwrap()
+    }
+
+    /// Read the raw data as an exFAT file name entry.
+    pub fn as_exfat_file_name_entry(&self) -> &ExFatFileNameDirEntry {
+        ExFatFileNameDirEntry::view(&self.data).unwrap()
+    }
+
+    /// Read the raw data as an exFAT allocation bitmap or up-case table entry.
+    pub fn as_exfat_metadata_entry(&self) -> &ExFatMetadataDirEntry {
+        ExFatMetadataDirEntry::view(&self.data).unwrap()
+    }
+
     /// Set the LFN checksum of the DOS name.
     pub fn set_lfn_checksum(&mut self, checksum: u8) {
         self.data[13] = checksum;
@@ -302,51 +481,45 @@ impl FatDirEntry {
     /// Retrieve the creation datetime of this 8.3 entry.
     pub fn get_creation_datetime(&self) -> FatDateTime {
         let entry = self.as_sfn_entry();
-        let raw_time = entry.creation_time.to_int();
-        let seconds = ((raw_time & 0x1f) << 1) as u8;
-        let minutes = ((raw_time >> 5) & 0x3f) as u8;
-        let hour = ((raw_time >> 11) & 0x1f) as u8;
-
-        let raw_date = entry.creation_date.to_int();
-        let day = (raw_date & 0x1f) as u8;
-        let month = ((raw_date >> 5) & 0xf) as u8;
-        let year = (raw_date >> 9) & 0x7f;
-        FatDateTime::new(
-            1980 + year,
-            month,
-            day,
-            hour,
-            minutes,
-            seconds,
-            self.data[13],
+        FatDateTime::from_raw(
+            entry.creation_date.to_int(),
+            entry.creation_time.to_int(),
+            entry.creation_tenths,
         )
     }
 
     /// Retrieve the last access datetime of this 8.3 entry.
     pub fn get_last_access_date(&self) -> FatDateTime {
         let entry = self.as_sfn_entry();
-
-        let raw_date = entry.last_access_date.to_int();
-        let day = (raw_date & 0x1f) as u8;
-        let month = ((raw_date >> 5) & 0xf) as u8;
-        let year = (raw_date >> 9) & 0x7f;
-        FatDateTime::new(1980 + year, month, day, 0, 0, 0, 0)
+        FatDateTime::from_raw(entry.last_access_date.to_int(), 0, 0)
     }
 
     /// Retrieve the last modification datetime of this 8.3 entry.
     pub fn get_modification_datetime(&self) -> FatDateTime {
         let entry = self.as_sfn_entry();
+        FatDateTime::from_raw(
+            entry.modification_date.to_int(),
+            entry.modification_time.to_int(),
+            0,
+        )
+    }
+
+    /// Set the creation datetime of this 8.3 entry.
+    pub fn set_creation_datetime(&mut self, datetime: &FatDateTime) {
+        self.data[13] = datetime.to_raw_tenths();
+        LittleEndian::write_u16(&mut self.data[14..16], datetime.to_raw_time());
+        LittleEndian::write_u16(&mut self.data[16..18], datetime.to_raw_date());
+    }
 
-        let raw_time = entry.modification_time.to_int();
-        let seconds = ((raw_time & 0x1f) << 1) as u8;
-        let minutes = ((raw_time >> 5) & 0x3f) as u8;
-        let hour = ((raw_time >> 11) & 0x1f) as u8;
+    /// Set the last access date of this 8.3 entry.
+    pub fn set_last_access_date(&mut self, datetime: &FatDateTime) {
+        LittleEndian::write_u16(&mut self.data[18..20], datetime.to_raw_date());
+    }
 
-        let raw_date = entry.modification_date.to_int();
-        let day = (raw_date & 0x1f) as u8;
-        let month = ((raw_date >> 5) & 0xf) as u8;
-        let year = (raw_date >> 9) & 0x7f;
-        FatDateTime::new(1980 + year, month, day, hour, minutes, seconds, 0)
+    /// Set the last modification datetime of this 8.3 entry.
+    pub fn set_modification_datetime(&mut self, datetime: &FatDateTime) {
+        LittleEndian::write_u16(&mut self.data[22..24], datetime.to_raw_time());
+        LittleEndian::write_u16(&mut self.data[24..26], datetime.to_raw_date());
     }
 }
 
@@ -356,11 +529,7 @@ impl<'a> core::fmt::Debug for FatDirEntry {
         write!(f, "{:?} ", self.attribute())?;
         if self.is_long_file_name() {
             if let Some(long_file_name) = self.long_file_name_raw() {
-                if let Some(data) = long_file_name.chars() {
-                    write!(f, "LongFileName {{{:?}}}", data)?;
-                } else {
-                    write!(f, "BROKEN LongFileName")?;
-                }
+                write!(f, "LongFileName {{{:?}}}", long_file_name.chars())?;
             } else {
                 write!(f, "LongFileName {{ \"not a long file name?????\" }}")?;
             }
@@ -2,6 +2,7 @@
 use crate::cluster::Cluster;
 use crate::filesystem::FatFileSystem;
 use crate::offset_iter::ClusterOffsetIter;
+use crate::table::FatClusterIter;
 
 use crate::FatError;
 use crate::FatFileSystemResult;
@@ -29,6 +30,9 @@ pub struct FatDirEntryIterator {
 
     /// Used at the first iteration to init the counter.
     pub is_first: bool,
+
+    /// Marker on exFAT directories with contiguous clusters not described by the FAT.
+    pub(crate) no_fat_chain: bool,
 }
 
 impl FatDirEntryIterator {
@@ -39,22 +43,34 @@ impl FatDirEntryIterator {
         cluster_offset: u64,
         offset: u64,
         in_root_directory: bool,
+        no_fat_chain: bool,
     ) -> Self {
+        let block_offset = cluster_offset / u64::from(fs.boot_record.bytes_per_block());
+
         let cluster_iter = if in_root_directory {
             match fs.boot_record.fat_type {
                 FatFsType::Fat12 | FatFsType::Fat16 => None,
-                FatFsType::Fat32 => Some(ClusterOffsetIter::new(
+                FatFsType::Fat32 | FatFsType::ExFat => Some(ClusterOffsetIter::new(
                     fs,
                     start_cluster,
-                    Some(cluster_offset),
+                    Some(block_offset),
                 )),
-                _ => unimplemented!(),
             }
+        } else if no_fat_chain {
+            // We don't know the size of the directory here, the end of the heap is used as limit.
+            Some(ClusterOffsetIter::from_cluster_iter(
+                fs,
+                FatClusterIter::new_contiguous(
+                    start_cluster,
+                    fs.boot_record.cluster_count - start_cluster.0,
+                ),
+                Some(block_offset),
+            ))
         } else {
             Some(ClusterOffsetIter::new(
                 fs,
                 start_cluster,
-                Some(cluster_offset),
+                Some(block_offset),
             ))
         };
 
@@ -64,6 +80,7 @@ impl FatDirEntryIterator {
             is_first: true,
             cluster_iter,
             last_cluster: None,
+            no_fat_chain,
         }
     }
 }
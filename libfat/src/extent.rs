@@ -0,0 +1,215 @@
+//! Extent cache of the cluster chains of files.
+
+use arrayvec::ArrayVec;
+
+use super::cluster::Cluster;
+use super::filesystem::FatFileSystem;
+use super::table;
+use super::table::FatClusterIter;
+use super::utils::FileSystemIterator;
+use super::FatFileSystemResult;
+use storage_device::StorageDevice;
+
+#[derive(Debug, Copy, Clone)]
+/// Represent a physically contiguous run of clusters of a file.
+struct Extent {
+    /// The index in the file of the first cluster of the extent.
+    file_cluster: u32,
+
+    /// The first cluster of the extent.
+    cluster: Cluster,
+
+    /// The count of clusters in the extent.
+    cluster_count: u32,
+}
+
+impl Extent {
+    /// Return the index in the file of the cluster following the extent.
+    fn end_file_cluster(&self) -> u32 {
+        self.file_cluster + self.cluster_count
+    }
+
+    /// Return the last cluster of the extent.
+    fn last_cluster(&self) -> Cluster {
+        Cluster(self.cluster.0 + self.cluster_count - 1)
+    }
+}
+
+/// Represent the cached extents of the cluster chain of a file.
+/// The extent holding a cluster is found with a binary search, only the first ``MAX_EXTENTS`` extents of a chain are cached.
+/// NOTE: When a chain is too fragmented to be fully cached, the clusters after the cached extents are found by following the chain,
+/// which costs a FAT lookup per cluster after the last cached extent.
+pub(crate) struct ExtentCache {
+    /// The extents of the start of the cluster chain, sorted by index in the file.
+    extents: ArrayVec<[Extent; ExtentCache::MAX_EXTENTS]>,
+
+    /// Set when the extents were loaded from the cluster chain.
+    is_loaded: bool,
+
+    /// Set when the cluster chain continues after the cached extents.
+    is_partial: bool,
+
+    /// The generation of the cluster chains of the filesystem when the extents were loaded.
+    generation: u32,
+}
+
+impl ExtentCache {
+    /// The max count of extents kept per file.
+    const MAX_EXTENTS: usize = 32;
+
+    /// Create an empty extent cache.
+    pub fn new() -> Self {
+        ExtentCache {
+            extents: ArrayVec::new(),
+            is_loaded: false,
+            is_partial: false,
+            generation: 0,
+        }
+    }
+
+    /// Return true if the extents were loaded from the cluster chain and no cluster chain was shortened or freed since.
+    pub fn is_valid<S: StorageDevice>(&self, fs: &FatFileSystem<S>) -> bool {
+        self.is_loaded && self.generation == fs.chain_generation()
+    }
+
+    /// Drop the cached extents, the cluster chain will be reloaded on the next access.
+    pub fn invalidate(&mut self) {
+        self.extents.clear();
+        self.is_loaded = false;
+        self.is_partial = false;
+    }
+
+    /// Load the extents of the first ``cluster_count`` clusters of a cluster chain.
+    pub fn load<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        mut cluster_iter: FatClusterIter,
+        cluster_count: u32,
+    ) {
+        self.invalidate();
+        self.is_loaded = true;
+        self.generation = fs.chain_generation();
+
+        for _ in 0..cluster_count {
+            match cluster_iter.next(fs) {
+                Some(cluster) => self.push(cluster),
+                None => break,
+            }
+
+            if self.is_partial {
+                break;
+            }
+        }
+    }
+
+    /// Append the next cluster of the cluster chain to the cached extents.
+    /// NOTE: This does nothing if the extents aren't loaded yet.
+    pub fn push(&mut self, cluster: Cluster) {
+        if !self.is_loaded || self.is_partial {
+            return;
+        }
+
+        if let Some(last_extent) = self.extents.last_mut() {
+            if last_extent.last_cluster().0 + 1 == cluster.0 {
+                last_extent.cluster_count += 1;
+                return;
+            }
+        }
+
+        let file_cluster = self
+            .extents
+            .last()
+            .map(Extent::end_file_cluster)
+            .unwrap_or(0);
+
+        let extent = Extent {
+            file_cluster,
+            cluster,
+            cluster_count: 1,
+        };
+
+        if self.extents.try_push(extent).is_err() {
+            self.is_partial = true;
+        }
+    }
+
+    /// Get the cluster at a given index in the file and the count of physically contiguous clusters starting at it.
+    /// At most ``max_cluster_count`` clusters are looked up after the cached extents.
+    /// Return None if the cluster chain is shorter than ``file_cluster``.
+    pub fn get_run<S: StorageDevice>(
+        &self,
+        fs: &FatFileSystem<S>,
+        file_cluster: u32,
+        max_cluster_count: u32,
+    ) -> Option<(Cluster, u32)> {
+        let extent_index = match self
+            .extents
+            .binary_search_by(|extent| extent.file_cluster.cmp(&file_cluster))
+        {
+            Ok(extent_index) => extent_index,
+            Err(0) => return None,
+            Err(extent_index) => extent_index - 1,
+        };
+
+        let extent = &self.extents[extent_index];
+        if file_cluster < extent.end_file_cluster() {
+            let offset = file_cluster - extent.file_cluster;
+            return Some((
+                Cluster(extent.cluster.0 + offset),
+                extent.cluster_count - offset,
+            ));
+        }
+
+        if !self.is_partial {
+            return None;
+        }
+
+        // Follow the chain after the cached extents.
+        let mut cluster_iter = FatClusterIter::new(fs, extent.last_cluster());
+        for _ in extent.end_file_cluster() - 1..file_cluster {
+            cluster_iter.next(fs)?;
+        }
+
+        let first_cluster = cluster_iter.next(fs)?;
+        let mut cluster_count = 1;
+
+        while cluster_count < max_cluster_count {
+            match cluster_iter.next(fs) {
+                Some(cluster) if cluster.0 == first_cluster.0 + cluster_count => {
+                    cluster_count += 1;
+                }
+                _ => break,
+            }
+        }
+
+        Some((first_cluster, cluster_count))
+    }
+
+    /// Get the last cluster of the cluster chain.
+    /// When ``is_fat_chain`` is set, the FAT is followed after the cached extents as another handle may have grown the cluster chain.
+    /// Return None if the cluster chain is empty.
+    /// NOTE: The clusters found after the cached extents aren't cached.
+    pub fn get_last_cluster<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        is_fat_chain: bool,
+    ) -> FatFileSystemResult<Option<Cluster>> {
+        let cached_last_cluster = match self.extents.last() {
+            Some(extent) => extent.last_cluster(),
+            None => return Ok(None),
+        };
+
+        if !is_fat_chain {
+            return Ok(Some(cached_last_cluster));
+        }
+
+        let last_cluster = table::get_last_cluster(fs, cached_last_cluster)?;
+
+        // The cluster chain continues after the cached extents.
+        if last_cluster != cached_last_cluster {
+            self.is_partial = true;
+        }
+
+        Ok(Some(last_cluster))
+    }
+}
@@ -4,13 +4,19 @@ use arrayvec::ArrayString;
 use byteorder::{ByteOrder, LittleEndian};
 
 use super::attribute::Attributes;
-use super::directory::{dir_entry::DirectoryEntry, Directory, File};
+use super::bitmap::AllocationBitmap;
+use super::datetime::{Clock, FatDateTime};
+use super::directory::{
+    dir_entry::DirectoryEntry, entry_set::ExFatEntrySet, raw_dir_entry::FatDirEntry, Directory,
+    File,
+};
 use super::offset_iter::ClusterOffsetIter;
 use super::FatVolumeBootRecord;
 
 use super::cluster::Cluster;
 use super::table;
-use super::table::FatValue;
+use super::table::{FatCache, FatValue};
+use super::upcase::UpcaseTable;
 use super::utils;
 use super::FatError;
 use super::FatFileSystemResult;
@@ -24,6 +30,7 @@ use core::sync::atomic::Ordering;
 use crate::utils::FileSystemIterator;
 
 /// Reprsent the FS Info structure of FAT32.
+/// NOTE: The FS Info is kept in memory, it is only written to the storage device when the filesystem is flushed.
 struct FatFileSystemInfo {
     // TODO: select Ordering wisely on operations.
     /// The last allocated cluster on the filesystem.
@@ -35,27 +42,28 @@ struct FatFileSystemInfo {
 
 impl FatFileSystemInfo {
     /// Import FS Info from a FAT32 filesystem.
+    /// NOTE: If the FS Info block is corrupted, the FS Info copy following the backup boot record is used.
     fn from_fs<S: StorageDevice>(fs: &FatFileSystem<S>) -> FatFileSystemResult<Self> {
         let mut block = [0x0u8; crate::MINIMAL_BLOCK_SIZE];
 
         let mut last_cluster = 0xFFFF_FFFF;
         let mut free_cluster = 0xFFFF_FFFF;
 
-        fs.storage_device
-            .lock()
-            .read(
-                fs.partition_start
-                    + u64::from(fs.boot_record.fs_info_block())
-                        * u64::from(fs.boot_record.bytes_per_block()),
+        Self::read_block(fs, fs.boot_record.fs_info_block(), &mut block)?;
+
+        let mut is_valid = Self::is_valid_block(&block);
+        let is_copy = !is_valid && fs.boot_record.backup_boot_record_block() != 0;
+
+        if is_copy {
+            Self::read_block(
+                fs,
+                fs.boot_record.backup_boot_record_block() + 1,
                 &mut block,
-            )
-            .or(Err(FatError::ReadFailed))?;
+            )?;
+            is_valid = Self::is_valid_block(&block);
+        }
 
-        // valid signature?
-        if &block[0..4] == b"RRaA"
-            && &block[0x1e4..0x1e8] == b"rrAa"
-            && LittleEndian::read_u16(&block[0x1fe..0x200]) == 0xAA55
-        {
+        if is_valid {
             // check cluster sanity
             let fs_last_cluster = LittleEndian::read_u32(&block[0x1ec..0x1f0]);
             if fs_last_cluster >= 2 && fs_last_cluster < fs.boot_record.cluster_count {
@@ -63,8 +71,9 @@ impl FatFileSystemInfo {
             }
 
             // check sanity
+            // NOTE: The copy isn't updated on every allocation so the free cluster count is computed again.
             let fs_free_cluster = LittleEndian::read_u32(&block[0x1e8..0x1ec]);
-            if fs_free_cluster <= fs.boot_record.cluster_count {
+            if fs_free_cluster <= fs.boot_record.cluster_count && !is_copy {
                 free_cluster = fs_free_cluster;
             }
         }
@@ -75,12 +84,35 @@ impl FatFileSystemInfo {
         })
     }
 
-    /// Flush the FS Info to the disk on FAT32 filesystems.
-    fn flush<S: StorageDevice>(&self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
-        if fs.boot_record.fat_type != FatFsType::Fat32 {
-            return Ok(());
-        }
+    /// Read a block of the reserved area.
+    fn read_block<S: StorageDevice>(
+        fs: &FatFileSystem<S>,
+        block_index: u16,
+        block: &mut [u8; crate::MINIMAL_BLOCK_SIZE],
+    ) -> FatFileSystemResult<()> {
+        fs.storage_device
+            .lock()
+            .read(
+                fs.partition_start
+                    + u64::from(block_index) * u64::from(fs.boot_record.bytes_per_block()),
+                block,
+            )
+            .or(Err(FatError::ReadFailed))
+    }
 
+    /// Check the signatures of a FS Info block.
+    fn is_valid_block(block: &[u8; crate::MINIMAL_BLOCK_SIZE]) -> bool {
+        &block[0..4] == b"RRaA"
+            && &block[0x1e4..0x1e8] == b"rrAa"
+            && LittleEndian::read_u16(&block[0x1fe..0x200]) == 0xAA55
+    }
+
+    /// Write the FS Info at a given block of the reserved area.
+    fn write<S: StorageDevice>(
+        &self,
+        fs: &FatFileSystem<S>,
+        block_index: u16,
+    ) -> FatFileSystemResult<()> {
         // We write a entire block because we want to ensure the data are correctly initialized.
         let mut block = [0x0u8; crate::MINIMAL_BLOCK_SIZE];
 
@@ -101,14 +133,79 @@ impl FatFileSystemInfo {
             .lock()
             .write(
                 fs.partition_start
-                    + u64::from(fs.boot_record.fs_info_block())
-                        * u64::from(fs.boot_record.bytes_per_block()),
+                    + u64::from(block_index) * u64::from(fs.boot_record.bytes_per_block()),
                 &block,
             )
             .or(Err(FatError::WriteFailed))?;
 
         Ok(())
     }
+
+    /// Flush the FS Info to the disk on FAT32 filesystems.
+    fn flush<S: StorageDevice>(&self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        if fs.boot_record.fat_type != FatFsType::Fat32 {
+            return Ok(());
+        }
+
+        self.write(fs, fs.boot_record.fs_info_block())
+    }
+
+    /// Flush the FS Info copy following the backup boot record on FAT32 filesystems.
+    fn flush_copy<S: StorageDevice>(&self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        if fs.boot_record.fat_type != FatFsType::Fat32
+            || fs.boot_record.backup_boot_record_block() == 0
+        {
+            return Ok(());
+        }
+
+        self.write(fs, fs.boot_record.backup_boot_record_block() + 1)
+    }
+}
+
+/// Represent the informations of a FAT volume.
+#[derive(Debug)]
+pub struct FatVolumeInfo {
+    /// The size in bytes of the data area of the volume.
+    pub total_size: u64,
+
+    /// The size in bytes of the free clusters of the volume.
+    pub free_size: u64,
+
+    /// The size of a cluster in bytes.
+    pub cluster_size: u32,
+
+    /// The FAT filesystem type.
+    pub fat_type: FatFsType,
+
+    /// The volume label. (empty if the volume doesn't have any label)
+    pub volume_label: ArrayString<[u8; FatVolumeInfo::MAX_LABEL_LEN_UNICODE]>,
+
+    /// The volume serial number.
+    pub volume_serial: u32,
+}
+
+impl FatVolumeInfo {
+    /// The max length of a volume label. (11 characters on FAT12/FAT16/FAT32 and 11 UTF-16 characters on exFAT)
+    pub const MAX_LABEL_LEN: usize = 11;
+
+    /// The max length of a volume label once encoded in UTF-8.
+    pub const MAX_LABEL_LEN_UNICODE: usize = Self::MAX_LABEL_LEN * 4;
+}
+
+/// Represent an entry differing between the active FAT and one of its copies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FatEntryMismatch {
+    /// The index of the FAT copy.
+    pub fat_index: u8,
+
+    /// The cluster of the entry.
+    pub cluster: u32,
+
+    /// The raw value of the entry in the active FAT.
+    pub active_value: u32,
+
+    /// The raw value of the entry in the FAT copy.
+    pub value: u32,
 }
 
 /// Represent a FAT filesystem.
@@ -132,6 +229,41 @@ pub struct FatFileSystem<S: StorageDevice> {
 
     /// The extra infos of the filesystem.
     fat_info: FatFileSystemInfo,
+
+    /// The allocation bitmap of the filesystem (exFAT only).
+    pub(crate) allocation_bitmap: AllocationBitmap,
+
+    /// The up-case table of the filesystem (exFAT only).
+    pub(crate) upcase_table: UpcaseTable,
+
+    /// The write-back cache of the FATs.
+    pub(crate) fat_cache: Mutex<FatCache>,
+
+    /// The index of the FAT used to read the cluster chains.
+    pub(crate) active_fat: u8,
+
+    /// The clock used to stamp directory entries.
+    clock: Option<&'static dyn Clock>,
+
+    /// Set when the volume was cleanly unmounted before this mount.
+    clean_shutdown: bool,
+
+    /// Set when a disk I/O error was recorded on the volume before this mount.
+    hard_error: bool,
+
+    /// Set when the volume was marked as dirty on the storage device.
+    /// NOTE: The lock is held while the volume status is updated on the storage device.
+    is_dirty: Mutex<bool>,
+
+    /// Set when entries with the read only attribute can be modified.
+    read_only_override: bool,
+
+    /// Incremented every time a cluster chain is shortened or freed, making the cached extents of the open files stale.
+    chain_generation: AtomicU32,
+
+    /// Held while entries are created, renamed or deleted, making the lookup of the name and the update of the entries atomic.
+    /// NOTE: The lock isn't reentrant, it must not be taken again while held.
+    dir_mutation_lock: Mutex<()>,
 }
 
 impl<S: StorageDevice> FatFileSystem<S> {
@@ -143,6 +275,12 @@ impl<S: StorageDevice> FatFileSystem<S> {
         partition_size: u64,
         boot_record: FatVolumeBootRecord,
     ) -> FatFileSystemResult<FatFileSystem<S>> {
+        let mut active_fat = boot_record.active_fat_index();
+        if active_fat >= boot_record.fats_count() {
+            warn!("Invalid active FAT {}, using the first FAT", active_fat);
+            active_fat = 0;
+        }
+
         let fs = FatFileSystem {
             storage_device: Mutex::new(storage_device),
             partition_start,
@@ -153,6 +291,17 @@ impl<S: StorageDevice> FatFileSystem<S> {
                 last_cluster: AtomicU32::new(0xFFFF_FFFF),
                 free_cluster: AtomicU32::new(0xFFFF_FFFF),
             },
+            allocation_bitmap: AllocationBitmap::empty(),
+            upcase_table: UpcaseTable::empty(),
+            fat_cache: Mutex::new(FatCache::new()),
+            active_fat,
+            clock: None,
+            clean_shutdown: true,
+            hard_error: false,
+            is_dirty: Mutex::new(false),
+            read_only_override: false,
+            chain_generation: AtomicU32::new(0),
+            dir_mutation_lock: Mutex::new(()),
         };
         Ok(fs)
     }
@@ -162,6 +311,14 @@ impl<S: StorageDevice> FatFileSystem<S> {
         // read FAT infos
         if self.boot_record.fat_type == FatFsType::Fat32 {
             self.fat_info = FatFileSystemInfo::from_fs(self)?;
+        } else if self.boot_record.fat_type == FatFsType::ExFat {
+            self.load_exfat_metadata()?;
+        }
+
+        if let Some((clean_shutdown_mask, hard_error_mask)) = self.get_volume_status_masks() {
+            let volume_status = FatValue::get_volume_status(self)?;
+            self.clean_shutdown = (volume_status & clean_shutdown_mask) != 0;
+            self.hard_error = (volume_status & hard_error_mask) == 0;
         }
 
         if self.fat_info.free_cluster.load(Ordering::SeqCst) == 0xFFFF_FFFF {
@@ -173,12 +330,57 @@ impl<S: StorageDevice> FatFileSystem<S> {
         Ok(())
     }
 
+    /// Load the allocation bitmap and the up-case table of an exFAT filesystem.
+    fn load_exfat_metadata(&mut self) -> FatFileSystemResult<()> {
+        let mut allocation_bitmap = None;
+        let mut upcase_table_info = None;
+
+        let mut fat_dir_entry_iter = self.get_root_directory().fat_dir_entry_iter();
+        while let Some(raw_dir_entry) = fat_dir_entry_iter.next(self) {
+            let raw_dir_entry = raw_dir_entry?;
+
+            // End of directory
+            if raw_dir_entry.is_free() {
+                break;
+            }
+
+            let metadata_entry = raw_dir_entry.as_exfat_metadata_entry();
+            let start_cluster = Cluster(metadata_entry.first_cluster.to_int());
+            let data_length = metadata_entry.data_length.to_int();
+
+            match raw_dir_entry.get_first_byte() {
+                // NOTE: A second bitmap is only present on TexFAT, we only use the first one.
+                FatDirEntry::EXFAT_ALLOCATION_BITMAP if (metadata_entry.flags & 0x1) == 0 => {
+                    allocation_bitmap = Some(AllocationBitmap::new(start_cluster, data_length));
+                }
+                FatDirEntry::EXFAT_UPCASE_TABLE => {
+                    upcase_table_info = Some((
+                        start_cluster,
+                        data_length,
+                        metadata_entry.table_checksum.to_int(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        let (start_cluster, data_length, checksum) =
+            upcase_table_info.ok_or(FatError::InvalidPartition)?;
+
+        self.allocation_bitmap = allocation_bitmap.ok_or(FatError::InvalidPartition)?;
+        self.upcase_table = UpcaseTable::load(self, start_cluster, data_length, checksum)?;
+
+        Ok(())
+    }
+
     /// Get the root directory of the filesystem.
     pub(crate) fn get_root_directory(&self) -> Directory<'_, S> {
         let dir_info = DirectoryEntry {
             start_cluster: self.boot_record.root_dir_childs_cluster(),
             raw_info: None,
             file_size: 0,
+            valid_data_length: 0,
+            no_fat_chain: false,
             creation_timestamp: 0,
             last_access_timestamp: 0,
             last_modification_timestamp: 0,
@@ -226,6 +428,10 @@ impl<S: StorageDevice> FatFileSystem<S> {
 
     /// Search for a directory entry inside the filesystem at the given path.
     pub fn search_entry(&self, path: &str) -> FatFileSystemResult<DirectoryEntry> {
+        if path.trim_matches('/').is_empty() {
+            return Ok(self.get_root_directory().dir_info);
+        }
+
         let (_, file_name) = utils::get_parent(path);
         self.open_parent_directory(path)?.search_entry(file_name)
     }
@@ -246,6 +452,8 @@ impl<S: StorageDevice> FatFileSystem<S> {
 
     /// Create a new directory at the given path.
     pub fn create_directory(&self, path: &str) -> FatFileSystemResult<()> {
+        let _dir_mutation = self.dir_mutation_lock.lock();
+
         let (_, file_name) = utils::get_parent(path);
         let mut parent_dir = self.open_parent_directory(path)?;
 
@@ -259,6 +467,8 @@ impl<S: StorageDevice> FatFileSystem<S> {
 
     /// Create a new file at the given path.
     pub fn create_file(&self, path: &str) -> FatFileSystemResult<()> {
+        let _dir_mutation = self.dir_mutation_lock.lock();
+
         let (_, file_name) = utils::get_parent(path);
         let mut parent_dir = self.open_parent_directory(path)?;
 
@@ -267,17 +477,45 @@ impl<S: StorageDevice> FatFileSystem<S> {
             return Err(FatError::FileExists);
         }
 
-        parent_dir.create_file(file_name)
+        parent_dir.create_file(file_name)?;
+
+        Ok(())
+    }
+
+    /// Open a file at the given path, creating it if it doesn't exist.
+    /// If ``exclusive`` is set, FatError::FileExists is returned when the file already exists.
+    pub fn open_or_create_file(&self, path: &str, exclusive: bool) -> FatFileSystemResult<File> {
+        let _dir_mutation = self.dir_mutation_lock.lock();
+
+        let (_, file_name) = utils::get_parent(path);
+        let mut parent_dir = self.open_parent_directory(path)?;
+
+        match parent_dir.find_entry(file_name) {
+            Ok(_) if exclusive => Err(FatError::FileExists),
+            Ok(file_info) => {
+                if file_info.attribute.is_directory() {
+                    return Err(FatError::NotAFile);
+                }
+
+                Ok(File::from_entry(file_info))
+            }
+            Err(FatError::NotFound) => parent_dir.create_file(file_name),
+            Err(err) => Err(err),
+        }
     }
 
     /// Delete a file at the given path.
     pub fn delete_file(&self, path: &str) -> FatFileSystemResult<()> {
+        let _dir_mutation = self.dir_mutation_lock.lock();
+
         let (_, file_name) = utils::get_parent(path);
         self.open_parent_directory(path)?.delete_file(file_name)
     }
 
     /// Delete a directory at the given path.
     pub fn delete_directory(&self, path: &str) -> FatFileSystemResult<()> {
+        let _dir_mutation = self.dir_mutation_lock.lock();
+
         let (_, file_name) = utils::get_parent(path);
         self.open_parent_directory(path)?
             .delete_directory(file_name)
@@ -293,13 +531,501 @@ impl<S: StorageDevice> FatFileSystem<S> {
         self.rename(old_path, new_path, true)
     }
 
+    /// Set the creation, last modification and last access UNIX timestamps of the entry at the given path.
+    /// NOTE: The timestamps are rounded down to the resolution of the filesystem.
+    pub fn set_file_timestamp(
+        &self,
+        path: &str,
+        creation_timestamp: u64,
+        modification_timestamp: u64,
+        access_timestamp: u64,
+    ) -> FatFileSystemResult<()> {
+        let entry = self.search_entry(path)?;
+
+        // The root directory doesn't have any timestamps.
+        let raw_info = entry.raw_info.ok_or(FatError::AccessDenied)?;
+
+        let creation_datetime = FatDateTime::from_unix_time(creation_timestamp);
+        let modification_datetime = FatDateTime::from_unix_time(modification_timestamp);
+        let access_datetime = FatDateTime::from_unix_time(access_timestamp);
+
+        if self.boot_record.fat_type == FatFsType::ExFat {
+            let mut entry_set = ExFatEntrySet::read(self, &raw_info)?;
+            entry_set.set_creation_datetime(&creation_datetime);
+            entry_set.set_modification_datetime(&modification_datetime);
+            entry_set.set_last_access_datetime(&access_datetime);
+            entry_set.flush(self)
+        } else {
+            let mut raw_dir_entry = raw_info.get_dir_entry(self)?;
+            raw_dir_entry.set_creation_datetime(&creation_datetime);
+            raw_dir_entry.set_modification_datetime(&modification_datetime);
+            raw_dir_entry.set_last_access_date(&access_datetime);
+            raw_dir_entry.flush(self)
+        }
+    }
+
+    /// Set the read only, hidden, system and archive attributes of the entry at the given path.
+    /// NOTE: The other attributes of the entry are preserved.
+    pub fn set_attributes(&self, path: &str, attributes: Attributes) -> FatFileSystemResult<()> {
+        let entry = self.search_entry(path)?;
+
+        // The root directory doesn't have any attributes.
+        let raw_info = entry.raw_info.ok_or(FatError::AccessDenied)?;
+
+        let attributes = Attributes::new(
+            (entry.attribute.get_value() & !Attributes::MODIFIABLE)
+                | (attributes.get_value() & Attributes::MODIFIABLE),
+        );
+
+        if self.boot_record.fat_type == FatFsType::ExFat {
+            let mut entry_set = ExFatEntrySet::read(self, &raw_info)?;
+            entry_set.set_attribute(attributes);
+            entry_set.flush(self)
+        } else {
+            let mut raw_dir_entry = raw_info.get_dir_entry(self)?;
+            raw_dir_entry.set_attribute(attributes);
+            raw_dir_entry.flush(self)
+        }
+    }
+
+    /// Write back all the cached FAT and allocation bitmap modifications and the FS Info to the storage device.
+    /// NOTE: Unlike sync, this doesn't mark the volume as cleanly unmounted.
+    pub fn flush(&self) -> FatFileSystemResult<()> {
+        self.allocation_bitmap.flush(self)?;
+        self.fat_cache.lock().flush(self)?;
+        self.fat_info.flush(self)
+    }
+
+    /// Write back all the pending modifications to the storage device.
+    /// If the volume was marked as dirty, it is then marked as cleanly unmounted.
+    /// NOTE: The next modification of the filesystem marks the volume as dirty again.
+    pub fn sync(&self) -> FatFileSystemResult<()> {
+        self.flush()?;
+
+        let mut is_dirty = self.is_dirty.lock();
+
+        if *is_dirty {
+            if let Some((clean_shutdown_mask, _)) = self.get_volume_status_masks() {
+                let volume_status = FatValue::get_volume_status(self)?;
+                FatValue::put_volume_status(self, volume_status | clean_shutdown_mask)?;
+                self.fat_cache.lock().flush(self)?;
+            }
+
+            *is_dirty = false;
+        }
+
+        Ok(())
+    }
+
+    /// Write back all the pending modifications, mark the volume as cleanly unmounted and release the storage device.
+    /// The storage device is always given back, along with the result of the write back.
+    pub fn unmount(self) -> (S, FatFileSystemResult<()>) {
+        let result = self.sync();
+
+        (self.storage_device.into_inner(), result)
+    }
+
+    /// Get the masks of the "clean shutdown" and "no hard error" bits in the second entry of the FAT.
+    /// NOTE: Only FAT16 and FAT32 keep those bits.
+    fn get_volume_status_masks(&self) -> Option<(u32, u32)> {
+        match self.boot_record.fat_type {
+            FatFsType::Fat16 => Some((0x8000, 0x4000)),
+            FatFsType::Fat32 => Some((0x0800_0000, 0x0400_0000)),
+            _ => None,
+        }
+    }
+
+    /// Mark the volume as dirty on the storage device if it isn't already.
+    /// This must be called before any modification reaches the storage device.
+    pub(crate) fn mark_dirty(&self) -> FatFileSystemResult<()> {
+        let mut is_dirty = self.is_dirty.lock();
+
+        if *is_dirty {
+            return Ok(());
+        }
+
+        if let Some((clean_shutdown_mask, _)) = self.get_volume_status_masks() {
+            let volume_status = FatValue::get_volume_status(self)?;
+            FatValue::put_volume_status(self, volume_status & !clean_shutdown_mask)?;
+            self.fat_cache.lock().flush(self)?;
+        }
+
+        // Only set once the volume is marked as dirty on the storage device.
+        *is_dirty = true;
+
+        Ok(())
+    }
+
+    /// Return true if the volume was cleanly unmounted before this mount.
+    /// NOTE: This is always true on FAT12 and exFAT as they don't keep track of it.
+    pub fn was_cleanly_unmounted(&self) -> bool {
+        self.clean_shutdown
+    }
+
+    /// Return true if a disk I/O error was recorded on the volume before this mount.
+    /// NOTE: This is always false on FAT12 and exFAT as they don't keep track of it.
+    pub fn has_hard_error(&self) -> bool {
+        self.hard_error
+    }
+
+    /// Write the backup boot record and the FS Info copy with the current boot record and FS Info.
+    /// NOTE: This does nothing on filesystems other than FAT32 as they don't have any backup.
+    pub fn refresh_backup_boot_record(&self) -> FatFileSystemResult<()> {
+        if self.boot_record.fat_type != FatFsType::Fat32 {
+            return Ok(());
+        }
+
+        self.flush()?;
+        self.boot_record.flush(self)?;
+        self.fat_info.flush_copy(self)
+    }
+
+    /// Set the clock used to stamp the creation, modification and access datetimes of entries.
+    pub fn set_clock(&mut self, clock: &'static dyn Clock) {
+        self.clock = Some(clock);
+    }
+
+    /// Allow or refuse the modification of entries with the read only attribute.
+    /// NOTE: Read only entries can't be written, resized, deleted or renamed unless this is enabled.
+    pub fn set_read_only_override(&mut self, read_only_override: bool) {
+        self.read_only_override = read_only_override;
+    }
+
+    /// Check if an entry with the given attributes can be modified.
+    /// Return an AccessDenied error if the entry is read only and the read only override isn't enabled.
+    pub(crate) fn check_modifiable(&self, attribute: Attributes) -> FatFileSystemResult<()> {
+        if attribute.is_read_only() && !self.read_only_override {
+            return Err(FatError::AccessDenied);
+        }
+
+        Ok(())
+    }
+
+    /// Get the current datetime from the clock.
+    /// NOTE: If no clock was set, the FAT epoch is returned.
+    pub(crate) fn now(&self) -> FatDateTime {
+        match self.clock {
+            Some(clock) => clock.now(),
+            None => FatDateTime::new(1980, 1, 1, 0, 0, 0, 0),
+        }
+    }
+
     /// Get the FAT filesystem type.
     pub fn get_type(&self) -> FatFsType {
         self.boot_record.fat_type
     }
 
+    /// Get the count of FATs of the filesystem.
+    pub fn get_fats_count(&self) -> u8 {
+        self.boot_record.fats_count()
+    }
+
+    /// Get the index of the FAT used to read the cluster chains.
+    pub fn get_active_fat(&self) -> u8 {
+        self.active_fat
+    }
+
+    /// Check if the modifications of the active FAT are written to all the FATs.
+    /// NOTE: This is always the case except on FAT32 filesystems with the mirroring disabled.
+    pub fn is_fat_mirroring_enabled(&self) -> bool {
+        self.boot_record.is_fat_mirroring_enabled()
+    }
+
+    /// Compare all the FAT copies with the active FAT.
+    /// ``report`` is called for every differing entry and the count of differing entries is returned.
+    pub fn compare_fats<F: FnMut(&FatEntryMismatch)>(
+        &self,
+        mut report: F,
+    ) -> FatFileSystemResult<u32> {
+        self.fat_cache.lock().flush(self)?;
+
+        let mut mismatch_count = 0;
+
+        for fat_index in 0..self.boot_record.fats_count() {
+            if fat_index == self.active_fat {
+                continue;
+            }
+
+            mismatch_count += table::compare_fat(
+                self,
+                u32::from(self.active_fat),
+                u32::from(fat_index),
+                |cluster, active_value, value| {
+                    report(&FatEntryMismatch {
+                        fat_index,
+                        cluster: cluster.0,
+                        active_value,
+                        value,
+                    })
+                },
+            )?;
+        }
+
+        Ok(mismatch_count)
+    }
+
+    /// Overwrite all the FATs with the content of a given FAT.
+    /// NOTE: The free cluster count is computed again as it may have changed.
+    pub fn resync_fats(&self, source_fat_index: u8) -> FatFileSystemResult<()> {
+        self.check_fat_index(source_fat_index)?;
+
+        let mut fat_cache = self.fat_cache.lock();
+        fat_cache.flush(self)?;
+
+        for fat_index in 0..self.boot_record.fats_count() {
+            if fat_index != source_fat_index {
+                table::copy_fat(self, u32::from(source_fat_index), u32::from(fat_index))?;
+            }
+        }
+
+        fat_cache.invalidate(self)?;
+        drop(fat_cache);
+
+        self.set_free_cluster_count(table::get_free_cluster_count(self)?);
+
+        Ok(())
+    }
+
+    /// Read the cluster chains from a given FAT, this permits to use a volume with a damaged first FAT.
+    /// NOTE: On FAT32 filesystems with the mirroring disabled, the active FAT is also changed in the boot record.
+    pub fn set_active_fat(&mut self, fat_index: u8) -> FatFileSystemResult<()> {
+        self.check_fat_index(fat_index)?;
+
+        self.fat_cache.lock().invalidate(self)?;
+        self.active_fat = fat_index;
+
+        if !self.boot_record.is_fat_mirroring_enabled() {
+            self.boot_record.set_active_fat_index(fat_index);
+            self.boot_record.flush(self)?;
+        }
+
+        self.set_free_cluster_count(table::get_free_cluster_count(self)?);
+
+        Ok(())
+    }
+
+    /// Check that a FAT index is valid on this filesystem.
+    fn check_fat_index(&self, fat_index: u8) -> FatFileSystemResult<()> {
+        if fat_index >= self.boot_record.fats_count() {
+            return Err(FatError::Custom {
+                name: "invalid FAT index",
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Get the size, free space, cluster size, type, label and serial number of the volume.
+    pub fn volume_info(&self) -> FatFileSystemResult<FatVolumeInfo> {
+        let cluster_size = self.boot_record.bytes_per_cluster();
+        let cluster_count = u64::from(self.boot_record.cluster_count - 2);
+        let free_cluster_count = u64::from(self.fat_info.free_cluster.load(Ordering::SeqCst));
+
+        Ok(FatVolumeInfo {
+            total_size: cluster_count * u64::from(cluster_size),
+            free_size: free_cluster_count * u64::from(cluster_size),
+            cluster_size,
+            fat_type: self.boot_record.fat_type,
+            volume_label: self.get_volume_label()?,
+            volume_serial: self.boot_record.volume_serial(),
+        })
+    }
+
+    /// Find the volume label entry of the root directory.
+    fn find_volume_label_entry(&self) -> FatFileSystemResult<Option<FatDirEntry>> {
+        let is_exfat = self.boot_record.fat_type == FatFsType::ExFat;
+
+        let mut fat_dir_entry_iter = self.get_root_directory().fat_dir_entry_iter();
+        while let Some(raw_dir_entry) = fat_dir_entry_iter.next(self) {
+            let raw_dir_entry = raw_dir_entry?;
+
+            // End of directory
+            if raw_dir_entry.is_free() {
+                break;
+            }
+
+            let is_volume_label = if is_exfat {
+                raw_dir_entry.get_first_byte() == FatDirEntry::EXFAT_VOLUME_LABEL
+            } else {
+                !raw_dir_entry.is_deleted()
+                    && !raw_dir_entry.is_long_file_name()
+                    && raw_dir_entry.attribute().is_volume()
+            };
+
+            if is_volume_label {
+                return Ok(Some(raw_dir_entry));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get the volume label.
+    /// NOTE: On FAT12/FAT16/FAT32, the root directory label entry is used if present, otherwise the boot record label is returned.
+    pub fn get_volume_label(
+        &self,
+    ) -> FatFileSystemResult<ArrayString<[u8; FatVolumeInfo::MAX_LABEL_LEN_UNICODE]>> {
+        let mut volume_label = ArrayString::new();
+        let volume_label_entry = self.find_volume_label_entry()?;
+
+        if self.boot_record.fat_type == FatFsType::ExFat {
+            if let Some(raw_dir_entry) = volume_label_entry {
+                let char_count =
+                    usize::min(raw_dir_entry.data[1] as usize, FatVolumeInfo::MAX_LABEL_LEN);
+                let utf16_label = (0..char_count).map(|index| {
+                    LittleEndian::read_u16(&raw_dir_entry.data[2 + index * 2..4 + index * 2])
+                });
+
+                for c in core::char::decode_utf16(utf16_label) {
+                    volume_label.push(c.unwrap_or(core::char::REPLACEMENT_CHARACTER));
+                }
+            }
+
+            return Ok(volume_label);
+        }
+
+        let raw_label = match volume_label_entry {
+            Some(raw_dir_entry) => {
+                let mut raw_label = [0x0u8; FatVolumeInfo::MAX_LABEL_LEN];
+                raw_label.copy_from_slice(&raw_dir_entry.data[0..FatVolumeInfo::MAX_LABEL_LEN]);
+                raw_label
+            }
+            None => self.boot_record.volume_label(),
+        };
+
+        for c in raw_label.iter() {
+            if c.is_ascii() {
+                volume_label.push(char::from(*c));
+            } else {
+                volume_label.push(core::char::REPLACEMENT_CHARACTER);
+            }
+        }
+
+        let len = volume_label.trim_end_matches(' ').len();
+        volume_label.truncate(len);
+
+        // "NO NAME" is the placeholder used when the volume doesn't have any label.
+        if volume_label.as_str() == "NO NAME" {
+            volume_label.clear();
+        }
+
+        Ok(volume_label)
+    }
+
+    /// Convert a volume label to its raw FAT12/FAT16/FAT32 representation. (uppercase and padded with spaces)
+    fn to_raw_volume_label(label: &str) -> FatFileSystemResult<[u8; FatVolumeInfo::MAX_LABEL_LEN]> {
+        let mut raw_label = [b' '; FatVolumeInfo::MAX_LABEL_LEN];
+
+        if label.len() > FatVolumeInfo::MAX_LABEL_LEN {
+            return Err(FatError::PathTooLong);
+        }
+
+        for (index, c) in label.bytes().enumerate() {
+            raw_label[index] = match c {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b' ' => c.to_ascii_uppercase(),
+                b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'(' | b')' | b'-' | b'@' | b'^'
+                | b'_' | b'`' | b'{' | b'}' | b'~' => c,
+                _ => {
+                    return Err(FatError::Custom {
+                        name: "invalid character in volume label",
+                    })
+                }
+            };
+        }
+
+        // A label cannot start with a space.
+        if raw_label[0] == b' ' && !label.is_empty() {
+            return Err(FatError::Custom {
+                name: "invalid character in volume label",
+            });
+        }
+
+        Ok(raw_label)
+    }
+
+    /// Set the volume label. An empty label removes the label of the volume.
+    /// NOTE: On FAT12/FAT16/FAT32, both the boot record label and the root directory label entry are updated.
+    pub fn set_volume_label(&mut self, label: &str) -> FatFileSystemResult<()> {
+        let volume_label_entry = self.find_volume_label_entry()?;
+
+        if self.boot_record.fat_type == FatFsType::ExFat {
+            if label.encode_utf16().count() > FatVolumeInfo::MAX_LABEL_LEN {
+                return Err(FatError::PathTooLong);
+            }
+
+            if label.is_empty() {
+                if let Some(mut raw_dir_entry) = volume_label_entry {
+                    raw_dir_entry.set_exfat_deleted();
+                    raw_dir_entry.flush(self)?;
+                }
+
+                return Ok(());
+            }
+
+            let mut raw_dir_entry = self.get_volume_label_entry(volume_label_entry)?;
+            raw_dir_entry.clear();
+            raw_dir_entry.data[0] = FatDirEntry::EXFAT_VOLUME_LABEL;
+
+            for (index, c) in label.encode_utf16().enumerate() {
+                raw_dir_entry.data[1] += 1;
+                LittleEndian::write_u16(&mut raw_dir_entry.data[2 + index * 2..4 + index * 2], c);
+            }
+
+            return raw_dir_entry.flush(self);
+        }
+
+        let raw_label = Self::to_raw_volume_label(label)?;
+
+        if label.is_empty() {
+            if let Some(mut raw_dir_entry) = volume_label_entry {
+                raw_dir_entry.set_deleted();
+                raw_dir_entry.flush(self)?;
+            }
+        } else {
+            let now = self.now();
+
+            let mut raw_dir_entry = self.get_volume_label_entry(volume_label_entry)?;
+            raw_dir_entry.clear();
+            (&mut raw_dir_entry.data[0..FatVolumeInfo::MAX_LABEL_LEN]).copy_from_slice(&raw_label);
+            raw_dir_entry.set_attribute(Attributes::new(Attributes::VOLUME));
+            raw_dir_entry.set_modification_datetime(&now);
+            raw_dir_entry.flush(self)?;
+        }
+
+        let boot_record_label = if label.is_empty() {
+            *b"NO NAME    "
+        } else {
+            raw_label
+        };
+
+        if self.boot_record.fat_type == FatFsType::Fat32 {
+            self.boot_record.set_volume_label32(boot_record_label);
+        } else {
+            self.boot_record.set_volume_label16(boot_record_label);
+        }
+
+        self.boot_record.flush(self)
+    }
+
+    /// Return the given volume label entry or allocate a new one in the root directory.
+    fn get_volume_label_entry(
+        &self,
+        volume_label_entry: Option<FatDirEntry>,
+    ) -> FatFileSystemResult<FatDirEntry> {
+        if let Some(raw_dir_entry) = volume_label_entry {
+            return Ok(raw_dir_entry);
+        }
+
+        let mut root_dir_info = self.get_root_directory().dir_info;
+        Directory::allocate_entries(&mut root_dir_info, self, 1)?
+            .next(self)
+            .ok_or(FatError::NoSpaceLeft)?
+    }
+
     /// Rename a directory or a file at the given path to a new path.
     fn rename(&self, old_path: &str, new_path: &str, is_dir: bool) -> FatFileSystemResult<()> {
+        let _dir_mutation = self.dir_mutation_lock.lock();
+
         let (_, file_name) = utils::get_parent(old_path);
         let parent_old_dir = self.open_parent_directory(old_path)?;
 
@@ -313,6 +1039,8 @@ impl<S: StorageDevice> FatFileSystem<S> {
             }
         }
 
+        self.check_modifiable(old_entry.attribute)?;
+
         let (_, file_name) = utils::get_parent(new_path);
         let parent_new_dir = self.open_parent_directory(new_path)?;
 
@@ -354,6 +1082,93 @@ impl<S: StorageDevice> FatFileSystem<S> {
         Ok(())
     }
 
+    /// Zero ``cluster_count`` physically contiguous clusters starting at a given cluster.
+    fn clean_contiguous_clusters(
+        &self,
+        cluster: Cluster,
+        cluster_count: u32,
+    ) -> FatFileSystemResult<()> {
+        let block = [0x0u8; crate::MINIMAL_BLOCK_SIZE];
+        let start = self.partition_start + cluster.to_data_bytes_offset(self);
+        let len = u64::from(cluster_count) * u64::from(self.boot_record.bytes_per_cluster());
+
+        let mut offset = 0;
+        while offset < len {
+            self.storage_device
+                .lock()
+                .write(start + offset, &block)
+                .or(Err(FatError::WriteFailed))?;
+
+            offset += block.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Get the free cluster count tracked by the filesystem.
+    pub(crate) fn get_free_cluster_count(&self) -> u32 {
+        self.fat_info.free_cluster.load(Ordering::SeqCst)
+    }
+
+    /// Set the free cluster count tracked by the filesystem.
+    /// NOTE: The FS Info is updated on the storage device when the filesystem is flushed.
+    pub(crate) fn set_free_cluster_count(&self, free_cluster_count: u32) {
+        self.fat_info
+            .free_cluster
+            .store(free_cluster_count, Ordering::SeqCst);
+    }
+
+    /// Get the current generation of the cluster chains, used to detect stale cached extents.
+    pub(crate) fn chain_generation(&self) -> u32 {
+        self.chain_generation.load(Ordering::SeqCst)
+    }
+
+    /// Mark the cached extents of all the open files as stale.
+    /// This must be called when a cluster chain is shortened or freed.
+    pub(crate) fn invalidate_extents(&self) {
+        self.chain_generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Check if a given cluster is free.
+    pub(crate) fn is_free_cluster(&self, cluster: Cluster) -> FatFileSystemResult<bool> {
+        // On exFAT, the FAT doesn't track clusters that aren't part of a FAT chain.
+        if self.boot_record.fat_type == FatFsType::ExFat {
+            self.allocation_bitmap.is_free(self, cluster)
+        } else {
+            Ok(FatValue::get(self, cluster)? == FatValue::Free)
+        }
+    }
+
+    /// Find the first free cluster in the ``[cluster, end_cluster)`` range.
+    pub(crate) fn find_free_cluster(
+        &self,
+        cluster: Cluster,
+        end_cluster: u32,
+    ) -> FatFileSystemResult<Option<Cluster>> {
+        if self.boot_record.fat_type == FatFsType::ExFat {
+            return self
+                .allocation_bitmap
+                .find(self, cluster, end_cluster, true);
+        }
+
+        table::find_cluster(self, cluster, end_cluster, true)
+    }
+
+    /// Find the first used cluster in the ``[cluster, end_cluster)`` range.
+    fn find_used_cluster(
+        &self,
+        cluster: Cluster,
+        end_cluster: u32,
+    ) -> FatFileSystemResult<Option<Cluster>> {
+        if self.boot_record.fat_type == FatFsType::ExFat {
+            return self
+                .allocation_bitmap
+                .find(self, cluster, end_cluster, false);
+        }
+
+        table::find_cluster(self, cluster, end_cluster, false)
+    }
+
     /// Allocate a cluster and if specified add it to a cluster chain.
     pub(crate) fn alloc_cluster(
         &self,
@@ -394,9 +1209,7 @@ impl<S: StorageDevice> FatFileSystem<S> {
                 number_cluster = 2;
             }
 
-            let value = FatValue::get(self, Cluster(number_cluster))?;
-
-            if value != FatValue::Free {
+            if !self.is_free_cluster(Cluster(number_cluster))? {
                 let new_start = Cluster(self.fat_info.last_cluster.load(Ordering::SeqCst));
                 if new_start.0 >= 2 && new_start.0 < self.boot_record.cluster_count {
                     start_cluster = new_start;
@@ -407,32 +1220,30 @@ impl<S: StorageDevice> FatFileSystem<S> {
         }
 
         if number_cluster == 0 {
-            number_cluster = start_cluster.0;
-            loop {
-                number_cluster += 1;
-                if number_cluster >= self.boot_record.cluster_count {
-                    number_cluster = 2;
-                    if number_cluster > start_cluster.0 {
-                        return Err(FatError::NoSpaceLeft);
-                    }
-                }
-
-                let value = FatValue::get(self, Cluster(number_cluster))?;
-
-                if value == FatValue::Free {
-                    break;
-                }
-
-                if number_cluster == start_cluster.0 {
-                    return Err(FatError::NoSpaceLeft);
-                }
-            }
+            let cluster_count = self.boot_record.cluster_count;
+
+            // Search after the start cluster and then wrap around to the beginning of the cluster heap.
+            let free_cluster =
+                match self.find_free_cluster(Cluster(start_cluster.0 + 1), cluster_count)? {
+                    Some(free_cluster) => Some(free_cluster),
+                    None => self.find_free_cluster(
+                        Cluster(2),
+                        core::cmp::min(start_cluster.0 + 1, cluster_count),
+                    )?,
+                };
+
+            number_cluster = free_cluster.ok_or(FatError::NoSpaceLeft)?.0;
         }
 
         let allocated_cluster = Cluster(number_cluster);
-        debug_assert!(FatValue::get(self, allocated_cluster)? == FatValue::Free);
+        debug_assert!(self.is_free_cluster(allocated_cluster)?);
         FatValue::put(self, allocated_cluster, FatValue::EndOfChain)?;
 
+        if self.boot_record.fat_type == FatFsType::ExFat {
+            self.allocation_bitmap
+                .set_used(self, allocated_cluster, true)?;
+        }
+
         // Link existing cluster with the new one availaible
         if let Some(last_cluster_allocated) = last_cluster_allocated_opt {
             debug_assert!(
@@ -450,17 +1261,125 @@ impl<S: StorageDevice> FatFileSystem<S> {
             .last_cluster
             .store(allocated_cluster.0, Ordering::SeqCst);
         self.fat_info.free_cluster.fetch_sub(1, Ordering::SeqCst);
-        self.fat_info.flush(self)?;
 
         Ok(allocated_cluster)
     }
 
+    /// Allocate ``cluster_count`` physically contiguous clusters and if specified add them to a cluster chain.
+    /// The clusters right after the end of the cluster chain are used when they are free.
+    /// When ``no_fat_chain`` is set on exFAT, the clusters are only marked as used in the allocation bitmap and the FAT is left untouched (exFAT "NoFatChain").
+    /// Return the first allocated cluster or a NoSpaceLeft error if no free run of clusters is large enough.
+    /// NOTE: With ``no_fat_chain``, the clusters must directly follow ``last_cluster_allocated_opt``, a NoSpaceLeft error is returned otherwise.
+    pub(crate) fn alloc_contiguous_clusters(
+        &self,
+        last_cluster_allocated_opt: Option<Cluster>,
+        cluster_count: u32,
+        no_fat_chain: bool,
+    ) -> FatFileSystemResult<Cluster> {
+        if cluster_count == 0 || cluster_count > self.fat_info.free_cluster.load(Ordering::SeqCst) {
+            return Err(FatError::NoSpaceLeft);
+        }
+
+        let is_exfat = self.boot_record.fat_type == FatFsType::ExFat;
+        let mut allocated_cluster = None;
+
+        // Try to extend the existing cluster chain in place.
+        if let Some(last_cluster_allocated) = last_cluster_allocated_opt {
+            let first_cluster = Cluster(last_cluster_allocated.0 + 1);
+            if self.is_free_cluster_run(first_cluster, cluster_count)? {
+                allocated_cluster = Some(first_cluster);
+            } else if no_fat_chain && is_exfat {
+                return Err(FatError::NoSpaceLeft);
+            }
+        }
+
+        // Otherwise, use the first free run of clusters large enough.
+        if allocated_cluster.is_none() {
+            let end_cluster = self.boot_record.cluster_count;
+            let mut cluster = Cluster(2);
+
+            while let Some(run_start) = self.find_free_cluster(cluster, end_cluster)? {
+                let run_end = u64::from(run_start.0) + u64::from(cluster_count);
+
+                // No later run can be large enough.
+                if run_end > u64::from(end_cluster) {
+                    break;
+                }
+
+                match self.find_used_cluster(run_start, run_end as u32)? {
+                    Some(used_cluster) => cluster = used_cluster,
+                    None => {
+                        allocated_cluster = Some(run_start);
+                        break;
+                    }
+                }
+            }
+        }
+
+        let allocated_cluster = allocated_cluster.ok_or(FatError::NoSpaceLeft)?;
+
+        // On FAT12/FAT16/FAT32, the clusters must not expose the data of deleted files. (exFAT relies on the valid data length)
+        if !is_exfat {
+            self.clean_contiguous_clusters(allocated_cluster, cluster_count)?;
+        }
+
+        if is_exfat {
+            for index in 0..cluster_count {
+                self.allocation_bitmap.set_used(
+                    self,
+                    Cluster(allocated_cluster.0 + index),
+                    true,
+                )?;
+            }
+        }
+
+        if !(no_fat_chain && is_exfat) {
+            table::link_contiguous_clusters(self, allocated_cluster, cluster_count)?;
+
+            // Link existing cluster with the new ones
+            if let Some(last_cluster_allocated) = last_cluster_allocated_opt {
+                FatValue::put(
+                    self,
+                    last_cluster_allocated,
+                    FatValue::Data(allocated_cluster.0),
+                )?;
+            }
+        }
+
+        self.fat_info
+            .last_cluster
+            .store(allocated_cluster.0 + cluster_count - 1, Ordering::SeqCst);
+        self.fat_info
+            .free_cluster
+            .fetch_sub(cluster_count, Ordering::SeqCst);
+
+        Ok(allocated_cluster)
+    }
+
+    /// Check if ``cluster_count`` clusters starting at a given cluster are all free.
+    pub(crate) fn is_free_cluster_run(
+        &self,
+        cluster: Cluster,
+        cluster_count: u32,
+    ) -> FatFileSystemResult<bool> {
+        let end_cluster = u64::from(cluster.0) + u64::from(cluster_count);
+        if end_cluster > u64::from(self.boot_record.cluster_count) {
+            return Ok(false);
+        }
+
+        Ok(self
+            .find_used_cluster(cluster, end_cluster as u32)?
+            .is_none())
+    }
+
     /// Free a cluster and if specified remove of a cluster chain.
     pub(crate) fn free_cluster(
         &self,
         to_remove: Cluster,
         previous_cluster: Option<Cluster>,
     ) -> FatFileSystemResult<()> {
+        self.invalidate_extents();
+
         if let Some(previous_cluster) = previous_cluster {
             FatValue::put(self, previous_cluster, FatValue::EndOfChain)?;
         }
@@ -476,6 +1395,11 @@ impl<S: StorageDevice> FatFileSystem<S> {
 
             FatValue::put(self, current_cluster, FatValue::Free)?;
 
+            if self.boot_record.fat_type == FatFsType::ExFat {
+                self.allocation_bitmap
+                    .set_used(self, current_cluster, false)?;
+            }
+
             // Invalidate last cluster if equals to the current cluster
             self.fat_info.last_cluster.compare_and_swap(
                 0xFFFF_FFFF,
@@ -492,7 +1416,24 @@ impl<S: StorageDevice> FatFileSystem<S> {
                 _ => break,
             }
         }
-        self.fat_info.flush(self)?;
+
+        Ok(())
+    }
+
+    /// Free ``cluster_count`` contiguous clusters that aren't described by the FAT (exFAT "NoFatChain").
+    pub(crate) fn free_contiguous_clusters(
+        &self,
+        cluster: Cluster,
+        cluster_count: u32,
+    ) -> FatFileSystemResult<()> {
+        self.invalidate_extents();
+
+        for index in 0..cluster_count {
+            self.allocation_bitmap
+                .set_used(self, Cluster(cluster.0 + index), false)?;
+            self.fat_info.free_cluster.fetch_add(1, Ordering::SeqCst);
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,577 @@
+//! FAT formatting.
+
+use super::table;
+use super::FatError;
+use super::FatFileSystemResult;
+use super::FatFsType;
+use super::FatVolumeBootRecord;
+use super::FAT32_BACKUP_BOOT_RECORD_BLOCK;
+use super::MINIMAL_BLOCK_SIZE;
+use storage_device::StorageDevice;
+
+/// Represent the layout of a FAT filesystem computed from the format options.
+struct FormatLayout {
+    /// The FAT filesystem type.
+    fat_type: FatFsType,
+
+    /// The amount of blocks per cluster.
+    blocks_per_cluster: u32,
+
+    /// The count of reserved blocks. (including the alignment padding)
+    reserved_block_count: u16,
+
+    /// The number of childs in the root directory.
+    root_dir_childs_count: u16,
+
+    /// The size in blocks of a FAT.
+    fat_size: u32,
+
+    /// The total block count of the filesystem.
+    block_count: u32,
+}
+
+/// Represent the parameters used to format a partition.
+#[derive(Clone, Copy, Debug)]
+pub struct FormatOptions<'a> {
+    /// The FAT filesystem type. (None to select it from the partition size)
+    fat_type: Option<FatFsType>,
+
+    /// The amount of bytes per block.
+    bytes_per_block: u16,
+
+    /// The amount of bytes per cluster. (None to select it from the partition size)
+    cluster_size: Option<u32>,
+
+    /// The number of FATs.
+    fats_count: u8,
+
+    /// The number of childs in the root directory. (FAT12/FAT16 only)
+    root_dir_childs_count: u16,
+
+    /// The count of reserved blocks. (None to use the default of the FAT type)
+    reserved_block_count: Option<u16>,
+
+    /// The media descriptor.
+    media_type: u8,
+
+    /// The OEM name. (padded with spaces)
+    oem_name: [u8; 8],
+
+    /// The volume serial number.
+    volume_serial: u32,
+
+    /// The volume label.
+    volume_label: &'a str,
+
+    /// The number of hidden blocks preceding the partition.
+    hidden_blocks: u32,
+
+    /// The alignment in blocks of the data area relative to the start of the partition.
+    alignment: u32,
+}
+
+impl<'a> FormatOptions<'a> {
+    /// Create the default format options for a given FAT filesystem type.
+    pub fn new(fat_type: FatFsType) -> Self {
+        FormatOptions {
+            fat_type: Some(fat_type),
+            bytes_per_block: MINIMAL_BLOCK_SIZE as u16,
+            cluster_size: None,
+            fats_count: 2,
+            root_dir_childs_count: 512,
+            reserved_block_count: None,
+            media_type: 0xF8,
+            oem_name: *b"MSWIN4.1",
+            volume_serial: 0,
+            volume_label: "",
+            hidden_blocks: 0,
+            alignment: 1,
+        }
+    }
+
+    /// Create the default format options selecting the FAT type and the cluster size from the size of the partition.
+    /// NOTE: This follows the Microsoft recommendations: FAT12 up to 4.1MB, FAT16 up to 512MB and FAT32 above.
+    pub fn automatic() -> Self {
+        let mut options = Self::new(FatFsType::Fat32);
+        options.fat_type = None;
+        options
+    }
+
+    /// Set the amount of bytes per cluster. Must be a power of two multiple of the block size.
+    pub fn cluster_size(mut self, cluster_size: u32) -> Self {
+        self.cluster_size = Some(cluster_size);
+        self
+    }
+
+    /// Set the number of FATs. (1 or 2)
+    pub fn fats_count(mut self, fats_count: u8) -> Self {
+        self.fats_count = fats_count;
+        self
+    }
+
+    /// Set the amount of bytes per block. (512, 1024, 2048 or 4096)
+    pub fn bytes_per_block(mut self, bytes_per_block: u16) -> Self {
+        self.bytes_per_block = bytes_per_block;
+        self
+    }
+
+    /// Set the number of childs in the root directory. Ignored on FAT32.
+    pub fn root_dir_childs_count(mut self, root_dir_childs_count: u16) -> Self {
+        self.root_dir_childs_count = root_dir_childs_count;
+        self
+    }
+
+    /// Set the count of reserved blocks. (Including the boot record)
+    pub fn reserved_block_count(mut self, reserved_block_count: u16) -> Self {
+        self.reserved_block_count = Some(reserved_block_count);
+        self
+    }
+
+    /// Set the media descriptor. (0xF0 or 0xF8 to 0xFF)
+    pub fn media_type(mut self, media_type: u8) -> Self {
+        self.media_type = media_type;
+        self
+    }
+
+    /// Set the OEM name. Names shorter than 8 bytes are padded with spaces.
+    pub fn oem_name(mut self, oem_name: &[u8]) -> Self {
+        self.oem_name = [b' '; 8];
+
+        for (index, c) in oem_name.iter().enumerate().take(self.oem_name.len()) {
+            self.oem_name[index] = *c;
+        }
+
+        self
+    }
+
+    /// Set the volume serial number.
+    pub fn volume_serial(mut self, volume_serial: u32) -> Self {
+        self.volume_serial = volume_serial;
+        self
+    }
+
+    /// Set the volume label. An empty label leave the volume without any label.
+    pub fn volume_label(mut self, volume_label: &'a str) -> Self {
+        self.volume_label = volume_label;
+        self
+    }
+
+    /// Set the number of hidden blocks preceding the partition.
+    pub fn hidden_blocks(mut self, hidden_blocks: u32) -> Self {
+        self.hidden_blocks = hidden_blocks;
+        self
+    }
+
+    /// Set the alignment in blocks of the data area relative to the start of the partition.
+    /// NOTE: The reserved area is extended to align the data area.
+    pub fn alignment(mut self, alignment: u32) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Check that the options are coherent for a given FAT type.
+    fn validate(&self, fat_type: FatFsType) -> FatFileSystemResult<()> {
+        if let FatFsType::ExFat = fat_type {
+            return Err(FatError::Custom {
+                name: "exFAT formatting isn't supported",
+            });
+        }
+
+        match self.bytes_per_block {
+            512 | 1024 | 2048 | 4096 => {}
+            _ => {
+                return Err(FatError::Custom {
+                    name: "invalid block size",
+                })
+            }
+        }
+
+        if let Some(cluster_size) = self.cluster_size {
+            let bytes_per_block = u32::from(self.bytes_per_block);
+
+            if !cluster_size.is_power_of_two()
+                || cluster_size < bytes_per_block
+                || cluster_size / bytes_per_block > 128
+                || cluster_size > 0x10000
+            {
+                return Err(FatError::Custom {
+                    name: "invalid cluster size",
+                });
+            }
+        }
+
+        if self.fats_count != 1 && self.fats_count != 2 {
+            return Err(FatError::Custom {
+                name: "invalid FAT count",
+            });
+        }
+
+        if fat_type != FatFsType::Fat32
+            && (self.root_dir_childs_count == 0
+                || (u32::from(self.root_dir_childs_count) * 32) % u32::from(self.bytes_per_block)
+                    != 0)
+        {
+            return Err(FatError::Custom {
+                name: "invalid root directory childs count",
+            });
+        }
+
+        if let Some(reserved_block_count) = self.reserved_block_count {
+            // FAT32 needs at least the boot record and the FS Info block.
+            let minimal_reserved_block_count = if fat_type == FatFsType::Fat32 { 2 } else { 1 };
+
+            if reserved_block_count < minimal_reserved_block_count {
+                return Err(FatError::Custom {
+                    name: "invalid reserved block count",
+                });
+            }
+        }
+
+        if self.media_type != 0xF0 && self.media_type < 0xF8 {
+            return Err(FatError::Custom {
+                name: "invalid media type",
+            });
+        }
+
+        if self.alignment == 0 {
+            return Err(FatError::Custom {
+                name: "invalid alignment",
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Select the FAT type recommended for a partition of ``partition_size`` bytes.
+    fn recommended_fat_type(partition_size: u64) -> FatFsType {
+        if partition_size <= 8400 * 512 {
+            FatFsType::Fat12
+        } else if partition_size < 512 * 1024 * 1024 {
+            FatFsType::Fat16
+        } else {
+            FatFsType::Fat32
+        }
+    }
+
+    /// Select the cluster size in bytes recommended for a FAT type on a partition of ``partition_size`` bytes.
+    fn recommended_cluster_size(fat_type: FatFsType, partition_size: u64) -> u32 {
+        const MB: u64 = 1024 * 1024;
+
+        match fat_type {
+            FatFsType::Fat32 => {
+                if partition_size <= 260 * MB {
+                    0x200
+                } else if partition_size <= 8 * 1024 * MB {
+                    0x1000
+                } else if partition_size <= 16 * 1024 * MB {
+                    0x2000
+                } else if partition_size <= 32 * 1024 * MB {
+                    0x4000
+                } else {
+                    0x8000
+                }
+            }
+            FatFsType::Fat16 => {
+                if partition_size <= 16 * MB {
+                    0x400
+                } else if partition_size <= 128 * MB {
+                    0x800
+                } else if partition_size <= 256 * MB {
+                    0x1000
+                } else if partition_size <= 512 * MB {
+                    0x2000
+                } else if partition_size <= 1024 * MB {
+                    0x4000
+                } else {
+                    0x8000
+                }
+            }
+            _ => {
+                // Use the smallest cluster keeping the cluster count in the range of FAT12.
+                let mut cluster_size = 0x200;
+                while cluster_size < 0x8000 && partition_size / u64::from(cluster_size) >= 0xFF5 {
+                    cluster_size *= 2;
+                }
+
+                cluster_size
+            }
+        }
+    }
+
+    /// Compute the size in bytes of a FAT holding ``entry_count`` entries.
+    fn fat_byte_size(fat_type: FatFsType, entry_count: u64) -> u64 {
+        match fat_type {
+            FatFsType::Fat12 => (entry_count * 3 + 1) / 2,
+            FatFsType::Fat16 => entry_count * 2,
+            _ => entry_count * 4,
+        }
+    }
+
+    /// Check if a given cluster count can be represented by the FAT type.
+    fn is_valid_cluster_count(fat_type: FatFsType, cluster_count: u64) -> bool {
+        match fat_type {
+            FatFsType::Fat12 => cluster_count < 0xFF5,
+            FatFsType::Fat16 => cluster_count >= 0xFF5 && cluster_count < 0xFFF5,
+            _ => cluster_count >= 0xFFF5 && cluster_count < 0x0FFF_FFF5,
+        }
+    }
+
+    /// Compute the layout of a given FAT type on a partition of ``partition_size`` bytes.
+    fn layout(
+        &self,
+        fat_type: FatFsType,
+        partition_size: u64,
+    ) -> FatFileSystemResult<FormatLayout> {
+        self.validate(fat_type)?;
+
+        let bytes_per_block = u64::from(self.bytes_per_block);
+        let block_count = partition_size / bytes_per_block;
+
+        if block_count > u64::from(core::u32::MAX) {
+            return Err(FatError::InvalidPartition);
+        }
+
+        let cluster_size = self.cluster_size.unwrap_or_else(|| {
+            u32::max(
+                Self::recommended_cluster_size(fat_type, partition_size),
+                u32::from(self.bytes_per_block),
+            )
+        });
+        let blocks_per_cluster = cluster_size / u32::from(self.bytes_per_block);
+
+        let is_fat32 = fat_type == FatFsType::Fat32;
+
+        let root_dir_childs_count = if is_fat32 {
+            0
+        } else {
+            self.root_dir_childs_count
+        };
+
+        let root_dir_blocks = (u64::from(root_dir_childs_count) * 32) / bytes_per_block;
+
+        let default_reserved_block_count = if is_fat32 { 32 } else { 1 };
+        let mut reserved_block_count = u64::from(
+            self.reserved_block_count
+                .unwrap_or(default_reserved_block_count),
+        );
+
+        // Grow the FAT until it can hold all the clusters of the data area.
+        let alignment = u64::from(self.alignment);
+        let mut fat_size = 1;
+        let mut cluster_count;
+        loop {
+            let metadata_blocks =
+                reserved_block_count + u64::from(self.fats_count) * fat_size + root_dir_blocks;
+            let padding = (alignment - metadata_blocks % alignment) % alignment;
+            let data_start = metadata_blocks + padding;
+
+            if data_start >= block_count {
+                return Err(FatError::InvalidPartition);
+            }
+
+            cluster_count = (block_count - data_start) / u64::from(blocks_per_cluster);
+
+            let needed_fat_size =
+                (Self::fat_byte_size(fat_type, cluster_count + 2) + bytes_per_block - 1)
+                    / bytes_per_block;
+
+            if needed_fat_size <= fat_size {
+                reserved_block_count += padding;
+                break;
+            }
+
+            fat_size = needed_fat_size;
+        }
+
+        if !Self::is_valid_cluster_count(fat_type, cluster_count) || reserved_block_count > 0xFFFF {
+            return Err(FatError::InvalidPartition);
+        }
+
+        Ok(FormatLayout {
+            fat_type,
+            blocks_per_cluster,
+            reserved_block_count: reserved_block_count as u16,
+            root_dir_childs_count,
+            fat_size: fat_size as u32,
+            block_count: block_count as u32,
+        })
+    }
+
+    /// Compute the layout of the filesystem on a partition of ``partition_size`` bytes.
+    /// If the FAT type isn't set, the recommended type is used if possible, otherwise the first type that can hold the partition.
+    fn select_layout(&self, partition_size: u64) -> FatFileSystemResult<FormatLayout> {
+        if let Some(fat_type) = self.fat_type {
+            return self.layout(fat_type, partition_size);
+        }
+
+        let recommended_fat_type = Self::recommended_fat_type(partition_size);
+        let mut result = self.layout(recommended_fat_type, partition_size);
+
+        for fat_type in [FatFsType::Fat32, FatFsType::Fat16, FatFsType::Fat12].iter() {
+            if result.is_ok() {
+                break;
+            }
+
+            result = self.layout(*fat_type, partition_size);
+        }
+
+        result
+    }
+}
+
+/// Format the partition with the given options.
+pub fn format_partition_with_options<S: StorageDevice>(
+    storage_device: S,
+    partition_start: u64,
+    partition_size: u64,
+    options: &FormatOptions<'_>,
+) -> FatFileSystemResult<()> {
+    let mut storage_device = storage_device;
+
+    let layout = options.select_layout(partition_size)?;
+    let fat_type = layout.fat_type;
+    let is_fat32 = fat_type == FatFsType::Fat32;
+
+    // Create an empty boot record
+    let mut boot_record = FatVolumeBootRecord::new_unchecked([0x0u8; MINIMAL_BLOCK_SIZE]);
+
+    let mut blocks_per_track = 63;
+    let mut heads = 255;
+
+    if partition_size < 512 * 1024 * 1024 {
+        blocks_per_track = 32;
+        heads = 64;
+    }
+
+    boot_record.set_oem_name(options.oem_name);
+    boot_record.set_media_type(options.media_type);
+    boot_record.set_num_heads(heads);
+    boot_record.set_blocks_per_track(blocks_per_track);
+    boot_record.set_blocks_per_cluster(layout.blocks_per_cluster as u8);
+    boot_record.set_fats_count(options.fats_count);
+    boot_record.set_bytes_per_block(options.bytes_per_block);
+    boot_record.set_hidden_blocks(options.hidden_blocks);
+    boot_record.set_root_dir_childs_count(layout.root_dir_childs_count);
+    boot_record.set_reserved_block_count(layout.reserved_block_count);
+    boot_record.set_total_blocks32(layout.block_count);
+
+    if is_fat32 {
+        boot_record.set_drive_number32(0x80);
+        boot_record.set_volume_serial32(options.volume_serial);
+        boot_record.set_volume_label32(*b"NO NAME    ");
+        boot_record.set_fat_size32(layout.fat_size);
+
+        // FAT32 specific features
+        boot_record.set_fs_info_block(1);
+
+        // The backup boot record is only written if there is enough reserved blocks to hold it and the FS Info copy.
+        if layout.reserved_block_count > FAT32_BACKUP_BOOT_RECORD_BLOCK + 1 {
+            boot_record.set_backup_boot_record_block(FAT32_BACKUP_BOOT_RECORD_BLOCK);
+        }
+
+        // Make sure to clean the fs info block as it may contains valid data
+        storage_device
+            .write(
+                partition_start
+                    + u64::from(boot_record.fs_info_block())
+                        * u64::from(boot_record.bytes_per_block()),
+                &[0x0; MINIMAL_BLOCK_SIZE],
+            )
+            .or(Err(FatError::WriteFailed))?;
+    } else {
+        boot_record.set_drive_number16(0x80);
+        boot_record.set_volume_serial16(options.volume_serial);
+        boot_record.set_volume_label16(*b"NO NAME    ");
+        boot_record.set_fat_size16(layout.fat_size as u16);
+    }
+
+    boot_record.set_valid(fat_type);
+
+    if !boot_record.is_valid() {
+        return Err(FatError::InvalidPartition);
+    }
+
+    // Init the boot_record as it should be valid now
+    boot_record.initialize_cache();
+    assert!(boot_record.fat_type == fat_type);
+
+    // Write the boot record for FatFilesystem creation
+    storage_device
+        .write(partition_start, &boot_record.data[..])
+        .or(Err(FatError::WriteFailed))?;
+
+    // Now we open the filesystem and clean the FATs while defering initalization.
+    let mut filesystem =
+        super::parse_fat_boot_record(storage_device, partition_start, partition_size, true)?;
+    table::FatValue::initialize(&filesystem)?;
+
+    // Now that the FATs are clean, we can init the filesystem (and the volume information on FAT32)
+    filesystem.init()?;
+
+    filesystem.create_root_directory()?;
+
+    if !options.volume_label.is_empty() {
+        filesystem.set_volume_label(options.volume_label)?;
+    }
+
+    // Rewrite the boot record as it might be updated
+    filesystem.boot_record.flush(&filesystem)?;
+
+    // Write back the FATs
+    filesystem.sync()?;
+
+    // Write the backup boot record and the FS Info copy on FAT32
+    filesystem.refresh_backup_boot_record()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FormatOptions;
+    use crate::FatFsType;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn recommended_fat_type_thresholds() {
+        let fat_type = FormatOptions::recommended_fat_type;
+
+        assert_eq!(fat_type(MB), FatFsType::Fat12);
+        assert_eq!(fat_type(8400 * 512), FatFsType::Fat12);
+        assert_eq!(fat_type(8400 * 512 + 1), FatFsType::Fat16);
+        assert_eq!(fat_type(512 * MB - 1), FatFsType::Fat16);
+        assert_eq!(fat_type(512 * MB), FatFsType::Fat32);
+        assert_eq!(fat_type(64 * 1024 * MB), FatFsType::Fat32);
+    }
+
+    #[test]
+    fn automatic_layout() {
+        let options = FormatOptions::automatic();
+
+        for (partition_size, fat_type) in [
+            (MB, FatFsType::Fat12),
+            (8400 * 512, FatFsType::Fat12),
+            (8400 * 512 + 512, FatFsType::Fat16),
+            (16 * MB, FatFsType::Fat16),
+            (512 * MB - 512, FatFsType::Fat16),
+            (512 * MB, FatFsType::Fat32),
+            (32 * 1024 * MB, FatFsType::Fat32),
+        ]
+        .iter()
+        {
+            let layout = options.select_layout(*partition_size).unwrap();
+            assert_eq!(layout.fat_type, *fat_type);
+        }
+    }
+
+    #[test]
+    fn automatic_layout_fallback() {
+        let options = FormatOptions::automatic().cluster_size(0x200);
+        let layout = options.select_layout(600 * MB).unwrap();
+        assert_eq!(layout.fat_type, FatFsType::Fat32);
+
+        // Too few clusters for FAT32, the first FAT type that can hold them is used.
+        let options = FormatOptions::automatic().cluster_size(0x8000);
+        let layout = options.select_layout(600 * MB).unwrap();
+        assert_eq!(layout.fat_type, FatFsType::Fat16);
+    }
+}
@@ -1,24 +1,29 @@
 //! The FAT library
-//! This crate currently supports FAT12/FAT16/FAT32 with a sector size >= 512 bytes.
+//! This crate currently supports FAT12/FAT16/FAT32 and exFAT with a sector size >= 512 bytes.
 #![no_std]
 
 pub mod attribute;
+mod bitmap;
+pub mod check;
 mod cluster;
-mod datetime;
+pub mod datetime;
 pub mod directory;
+mod extent;
 pub mod filesystem;
+pub mod format;
 mod name;
 mod offset_iter;
 mod table;
+mod upcase;
 mod utils;
 
 use byteorder::{ByteOrder, LittleEndian};
-use storage_device::Block;
 use storage_device::StorageDevice;
 
 use cluster::Cluster;
 
 use filesystem::FatFileSystem;
+use format::{format_partition_with_options, FormatOptions};
 
 /// The minimal block size supported.
 pub const MINIMAL_BLOCK_SIZE: usize = 512;
@@ -114,6 +119,15 @@ impl FatVolumeBootRecord {
     /// Offset of the FAT32 system identifier.
     const SYSTEM_IDENTIFIER_FAT32: usize = 82;
 
+    /// Offset of the exFAT filesystem name.
+    const SYSTEM_IDENTIFIER_EXFAT: usize = 3;
+
+    /// Mask of the active FAT index in the FAT32 extended flags.
+    const FAT32_ACTIVE_FAT_MASK: u16 = 0xF;
+
+    /// Bit of the FAT32 extended flags set when only the active FAT is updated.
+    const FAT32_MIRRORING_DISABLED: u16 = 0x80;
+
     /// Create a new FAT volume boot record from raw data.
     pub fn new(data: [u8; MINIMAL_BLOCK_SIZE]) -> Option<Self> {
         let mut res = Self::new_unchecked(data);
@@ -129,6 +143,12 @@ impl FatVolumeBootRecord {
 
     /// Initialize extra infos computed from the data backend.
     pub fn initialize_cache(&mut self) {
+        if self.is_exfat() {
+            self.fat_type = FatFsType::ExFat;
+            self.cluster_count = self.exfat_cluster_count() + 2;
+            return;
+        }
+
         let root_dir_blocks = ((u32::from(self.root_dir_childs_count()) * 32)
             + (u32::from(self.bytes_per_block()) - 1))
             / u32::from(self.bytes_per_block());
@@ -171,6 +191,10 @@ impl FatVolumeBootRecord {
             return false;
         }
 
+        if self.is_exfat() {
+            return self.is_valid_exfat();
+        }
+
         // check system identifier
         if self.data[Self::SYSTEM_IDENTIFIER_FAT..Self::SYSTEM_IDENTIFIER_FAT + 3] != *b"FAT"
             && self.data[Self::SYSTEM_IDENTIFIER_FAT32..Self::SYSTEM_IDENTIFIER_FAT32 + 5]
@@ -186,6 +210,35 @@ impl FatVolumeBootRecord {
         true
     }
 
+    /// Checks the validity of an exFAT boot record.
+    fn is_valid_exfat(&self) -> bool {
+        // The legacy BIOS parameter block must be zeroed on exFAT.
+        if self.data[11..64].iter().any(|value| *value != 0) {
+            return false;
+        }
+
+        let bytes_per_block_shift = self.data[108];
+        if bytes_per_block_shift < 9 || bytes_per_block_shift > 12 {
+            return false;
+        }
+
+        // A cluster cannot be bigger than 32MiB.
+        if self.data[109] > 25 - bytes_per_block_shift {
+            return false;
+        }
+
+        if self.fats_count() != 1 && self.fats_count() != 2 {
+            return false;
+        }
+
+        self.exfat_cluster_count() != 0
+    }
+
+    /// Check if the boot record describe an exFAT filesystem.
+    pub fn is_exfat(&self) -> bool {
+        self.data[Self::SYSTEM_IDENTIFIER_EXFAT..Self::SYSTEM_IDENTIFIER_EXFAT + 8] == *b"EXFAT   "
+    }
+
     /// Mark the boot record as valid
     pub(crate) fn set_valid(&mut self, fat_type: FatFsType) {
         // First boot signature
@@ -194,18 +247,40 @@ impl FatVolumeBootRecord {
             0xAA55,
         );
 
-        if let FatFsType::Fat32 = fat_type {
-            (&mut self.data[Self::SYSTEM_IDENTIFIER_FAT32..Self::SYSTEM_IDENTIFIER_FAT32 + 5])
-                .copy_from_slice(b"FAT32");
-        } else {
-            (&mut self.data[Self::SYSTEM_IDENTIFIER_FAT..Self::SYSTEM_IDENTIFIER_FAT + 3])
-                .copy_from_slice(b"FAT");
+        match fat_type {
+            FatFsType::Fat32 => (&mut self.data
+                [Self::SYSTEM_IDENTIFIER_FAT32..Self::SYSTEM_IDENTIFIER_FAT32 + 8])
+                .copy_from_slice(b"FAT32   "),
+            FatFsType::Fat16 => (&mut self.data
+                [Self::SYSTEM_IDENTIFIER_FAT..Self::SYSTEM_IDENTIFIER_FAT + 8])
+                .copy_from_slice(b"FAT16   "),
+            _ => (&mut self.data[Self::SYSTEM_IDENTIFIER_FAT..Self::SYSTEM_IDENTIFIER_FAT + 8])
+                .copy_from_slice(b"FAT12   "),
         }
 
         // Set jump for signature
         (&mut self.data[0..3]).copy_from_slice(&[0xEB, 0xFE, 0x90]);
     }
 
+    /// Set the OEM name.
+    pub(crate) fn set_oem_name(&mut self, oem_name: [u8; 8]) {
+        (&mut self.data[3..11]).copy_from_slice(&oem_name)
+    }
+
+    /// Set the volume serial number for a FAT12/FAT16 filesystem.
+    /// NOTE: This also set the extended boot signature as the serial number and the label are only valid with it.
+    pub(crate) fn set_volume_serial16(&mut self, volume_serial: u32) {
+        self.data[38] = 0x29;
+        LittleEndian::write_u32(&mut self.data[39..43], volume_serial);
+    }
+
+    /// Set the volume serial number for a FAT32 filesystem.
+    /// NOTE: This also set the extended boot signature as the serial number and the label are only valid with it.
+    pub(crate) fn set_volume_serial32(&mut self, volume_serial: u32) {
+        self.data[66] = 0x29;
+        LittleEndian::write_u32(&mut self.data[67..71], volume_serial);
+    }
+
     /// Set the volume label for a FAT12/FAT16 filesystem.
     pub(crate) fn set_volume_label16(&mut self, label: [u8; 11]) {
         (&mut self.data[43..54]).copy_from_slice(&label)
@@ -216,9 +291,35 @@ impl FatVolumeBootRecord {
         (&mut self.data[71..82]).copy_from_slice(&label)
     }
 
+    /// The volume label on a FAT12/FAT16/FAT32 filesystem.
+    pub fn volume_label(&self) -> [u8; 11] {
+        let mut label = [0x0u8; 11];
+
+        if let FatFsType::Fat32 = self.fat_type {
+            label.copy_from_slice(&self.data[71..82]);
+        } else {
+            label.copy_from_slice(&self.data[43..54]);
+        }
+
+        label
+    }
+
+    /// The volume serial number.
+    pub fn volume_serial(&self) -> u32 {
+        match self.fat_type {
+            FatFsType::ExFat => LittleEndian::read_u32(&self.data[100..104]),
+            FatFsType::Fat32 => LittleEndian::read_u32(&self.data[67..71]),
+            _ => LittleEndian::read_u32(&self.data[39..43]),
+        }
+    }
+
     /// The amount of bytes per block.
     pub fn bytes_per_block(&self) -> u16 {
-        LittleEndian::read_u16(&self.data[11..13])
+        if self.is_exfat() {
+            1 << self.data[108]
+        } else {
+            LittleEndian::read_u16(&self.data[11..13])
+        }
     }
 
     /// Set the amount of bytes per block.
@@ -227,8 +328,17 @@ impl FatVolumeBootRecord {
     }
 
     /// The amount of blocks per cluster.
-    pub fn blocks_per_cluster(&self) -> u8 {
-        self.data[13]
+    pub fn blocks_per_cluster(&self) -> u32 {
+        if self.is_exfat() {
+            1 << self.data[109]
+        } else {
+            u32::from(self.data[13])
+        }
+    }
+
+    /// The amount of bytes per cluster.
+    pub fn bytes_per_cluster(&self) -> u32 {
+        self.blocks_per_cluster() * u32::from(self.bytes_per_block())
     }
 
     /// Set the amount of blocks per cluster.
@@ -248,7 +358,11 @@ impl FatVolumeBootRecord {
 
     /// The number of FAT present in the filesystem.
     pub fn fats_count(&self) -> u8 {
-        self.data[16]
+        if self.is_exfat() {
+            self.data[110]
+        } else {
+            self.data[16]
+        }
     }
 
     /// Set the number of FAT present in the filesystem.
@@ -331,6 +445,11 @@ impl FatVolumeBootRecord {
         LittleEndian::read_u32(&self.data[28..32])
     }
 
+    /// Set the number of hidden blocks on the FAT filesystem.
+    pub(crate) fn set_hidden_blocks(&mut self, hidden_blocks: u32) {
+        LittleEndian::write_u32(&mut self.data[28..32], hidden_blocks);
+    }
+
     /// The total block count on a FAT32 filesystem.
     pub fn total_blocks32(&self) -> u32 {
         LittleEndian::read_u32(&self.data[32..36])
@@ -351,6 +470,40 @@ impl FatVolumeBootRecord {
         LittleEndian::write_u32(&mut self.data[36..40], fat_size);
     }
 
+    /// The FAT32 extended flags (active FAT and mirroring state).
+    pub fn fat32_extended_flags(&self) -> u16 {
+        LittleEndian::read_u16(&self.data[40..42])
+    }
+
+    /// Set the FAT32 extended flags.
+    pub(crate) fn set_fat32_extended_flags(&mut self, extended_flags: u16) {
+        LittleEndian::write_u16(&mut self.data[40..42], extended_flags);
+    }
+
+    /// Check if the modifications of the FAT are written to all the FATs.
+    /// NOTE: Only FAT32 can disable the mirroring.
+    pub fn is_fat_mirroring_enabled(&self) -> bool {
+        self.fat_type != FatFsType::Fat32
+            || (self.fat32_extended_flags() & Self::FAT32_MIRRORING_DISABLED) == 0
+    }
+
+    /// The index of the FAT used when the mirroring is disabled.
+    pub fn active_fat_index(&self) -> u8 {
+        if self.is_fat_mirroring_enabled() {
+            0
+        } else {
+            (self.fat32_extended_flags() & Self::FAT32_ACTIVE_FAT_MASK) as u8
+        }
+    }
+
+    /// Set the index of the FAT used when the mirroring is disabled.
+    pub(crate) fn set_active_fat_index(&mut self, fat_index: u8) {
+        let extended_flags = (self.fat32_extended_flags() & !Self::FAT32_ACTIVE_FAT_MASK)
+            | (u16::from(fat_index) & Self::FAT32_ACTIVE_FAT_MASK);
+
+        self.set_fat32_extended_flags(extended_flags);
+    }
+
     /// The block index of the FAT32's filesystem informations.
     pub fn fs_info_block(&self) -> u16 {
         LittleEndian::read_u16(&self.data[48..50])
@@ -371,9 +524,13 @@ impl FatVolumeBootRecord {
         LittleEndian::write_u16(&mut self.data[50..52], backup_boot_record_block);
     }
 
-    /// The root directory cluster for FAT32 filesystems.
+    /// The root directory cluster for FAT32 and exFAT filesystems.
     pub fn root_dir_childs_cluster(&self) -> Cluster {
-        Cluster(LittleEndian::read_u32(&self.data[44..48]))
+        if self.is_exfat() {
+            Cluster(LittleEndian::read_u32(&self.data[96..100]))
+        } else {
+            Cluster(LittleEndian::read_u32(&self.data[44..48]))
+        }
     }
 
     /// Set the root directory cluster for FAT32 filesystem.
@@ -381,8 +538,31 @@ impl FatVolumeBootRecord {
         LittleEndian::write_u32(&mut self.data[44..48], cluster.0);
     }
 
+    /// The block index of the first FAT.
+    pub fn fat_start_block(&self) -> u32 {
+        if self.is_exfat() {
+            LittleEndian::read_u32(&self.data[80..84])
+        } else {
+            u32::from(self.reserved_block_count())
+        }
+    }
+
+    /// The block index of the exFAT cluster heap.
+    pub fn exfat_cluster_heap_block(&self) -> u32 {
+        LittleEndian::read_u32(&self.data[88..92])
+    }
+
+    /// The count of clusters in the exFAT cluster heap.
+    pub fn exfat_cluster_count(&self) -> u32 {
+        LittleEndian::read_u32(&self.data[92..96])
+    }
+
     /// Return the size in blocks of the FAT.
     pub fn fat_size(&self) -> u32 {
+        if self.is_exfat() {
+            return LittleEndian::read_u32(&self.data[84..88]);
+        }
+
         let result = u32::from(self.fat_size16());
         if result != 0 {
             result
@@ -410,7 +590,7 @@ impl FatVolumeBootRecord {
             .or(Err(FatError::WriteFailed))?;
 
         // On FAT32, we need to write the backup boot record.
-        if let FatFsType::Fat32 = self.fat_type {
+        if self.fat_type == FatFsType::Fat32 && self.backup_boot_record_block() != 0 {
             fs.storage_device
                 .lock()
                 .write(
@@ -426,24 +606,45 @@ impl FatVolumeBootRecord {
     }
 }
 
+/// The block index of the FAT32's Boot Record Backup used to recover a corrupted boot record.
+const FAT32_BACKUP_BOOT_RECORD_BLOCK: u16 = 6;
+
 /// Get a FAT boot record from a StorageDevice.
+/// If the boot record is corrupted, the FAT32 backup boot record is used and the second value returned is true.
 fn get_fat_boot_record(
     storage_device: &mut dyn StorageDevice,
     partition_start: u64,
-) -> FatFileSystemResult<FatVolumeBootRecord> {
+) -> FatFileSystemResult<(FatVolumeBootRecord, bool)> {
     let mut block = [0x0u8; MINIMAL_BLOCK_SIZE];
 
     storage_device
         .read(partition_start, &mut block)
         .or(Err(FatError::ReadFailed))?;
 
-    let boot_record = FatVolumeBootRecord::new(block);
+    if let Some(boot_record) = FatVolumeBootRecord::new(block) {
+        return Ok((boot_record, false));
+    }
 
-    if boot_record.is_none() {
-        return Err(FatError::InvalidPartition);
+    // The block size is unknown, try all the supported ones.
+    for bytes_per_block in [512, 1024, 2048, 4096].iter() {
+        storage_device
+            .read(
+                partition_start + u64::from(FAT32_BACKUP_BOOT_RECORD_BLOCK) * bytes_per_block,
+                &mut block,
+            )
+            .or(Err(FatError::ReadFailed))?;
+
+        if let Some(boot_record) = FatVolumeBootRecord::new(block) {
+            if boot_record.fat_type == FatFsType::Fat32
+                && u64::from(boot_record.bytes_per_block()) == *bytes_per_block
+                && boot_record.backup_boot_record_block() == FAT32_BACKUP_BOOT_RECORD_BLOCK
+            {
+                return Ok((boot_record, true));
+            }
+        }
     }
 
-    Ok(boot_record.unwrap())
+    Err(FatError::InvalidPartition)
 }
 
 /// Parse a FAT boot record and return a FatFileSystem instance.
@@ -454,10 +655,28 @@ fn parse_fat_boot_record<S: StorageDevice>(
     uninitialized: bool,
 ) -> FatFileSystemResult<FatFileSystem<S>> {
     let mut storage_device = storage_device;
-    let boot_record = get_fat_boot_record(&mut storage_device, partition_start)?;
+    let (boot_record, is_backup_boot_record) =
+        get_fat_boot_record(&mut storage_device, partition_start)?;
 
     match boot_record.fat_type {
-        FatFsType::ExFat => unimplemented!(),
+        FatFsType::ExFat => {
+            let first_data_offset = u64::from(boot_record.exfat_cluster_heap_block())
+                * u64::from(boot_record.bytes_per_block());
+
+            let mut file_system = FatFileSystem::new(
+                storage_device,
+                partition_start,
+                first_data_offset,
+                partition_size,
+                boot_record,
+            )?;
+
+            if !uninitialized {
+                file_system.init()?;
+            }
+
+            Ok(file_system)
+        }
         FatFsType::Fat12 | FatFsType::Fat16 | FatFsType::Fat32 => {
             // Zero on FAT32
             let root_dir_blocks = ((u32::from(boot_record.root_dir_childs_count()) * 32)
@@ -478,6 +697,12 @@ fn parse_fat_boot_record<S: StorageDevice>(
 
             if !uninitialized {
                 file_system.init()?;
+
+                // Restore the boot record from its backup.
+                if is_backup_boot_record {
+                    warn!("FAT32 boot record is corrupted, restoring it from its backup");
+                    file_system.boot_record.flush(&file_system)?;
+                }
             }
 
             Ok(file_system)
@@ -514,6 +739,15 @@ pub fn format_raw_partition<S: StorageDevice>(
     format_partition(storage_device, fat_type, 0, storage_size)
 }
 
+/// Format the given storage selecting the FAT type and the cluster size from its size.
+pub fn format_raw_partition_automatic<S: StorageDevice>(
+    mut storage_device: S,
+) -> FatFileSystemResult<()> {
+    let storage_size = storage_device.len().or(Err(FatError::ReadFailed))?;
+
+    format_partition_with_options(storage_device, 0, storage_size, &FormatOptions::automatic())
+}
+
 /// Format the partition to hold a given FAT filesystem type.
 pub fn format_partition<S: StorageDevice>(
     storage_device: S,
@@ -521,143 +755,32 @@ pub fn format_partition<S: StorageDevice>(
     partition_start: u64,
     partition_size: u64
 ) -> FatFileSystemResult<()> {
-    let mut storage_device = storage_device;
-
-    // Create an empty boot record
-    let mut boot_record = FatVolumeBootRecord::new_unchecked([0x0u8; MINIMAL_BLOCK_SIZE]);
-
-    let mut blocks_per_track = 63;
-    let mut heads = 255;
-    let mut cluster_size = 4;
-
-    let block_count = partition_size / Block::LEN_U64;
-
-    if partition_size < 512 * 1024 * 1024 {
-        blocks_per_track = 32;
-        heads = 64;
-    }
-
-    let number_clusters = block_count / u64::from(cluster_size);
-
-    if let FatFsType::Fat32 = fat_type {
-        let size_mb = partition_size / (1024 * 1024);
-        if size_mb > 32 * 1024 {
-            cluster_size = 64;
-        } else if size_mb > 16 * 1024 {
-            cluster_size = 32;
-        } else if size_mb > 8 * 1024 {
-            cluster_size = 16;
-        } else if size_mb > 1 * 1024 {
-            cluster_size = 8;
-        } else {
-            cluster_size = 1;
-        }
-    } else if let FatFsType::Fat16 = fat_type {
-        // If we have too many clusters, try to make it in range of the FAT16
-        if number_clusters > 0xFFF5 {
-            cluster_size = 64;
-        }
-    } else {
-        // If we have too many clusters, try to make it in range of the FAT12
-        if number_clusters > 0xFF5 {
-            cluster_size = 64;
-        }
-    }
-
-    let number_clusters = block_count / u64::from(cluster_size);
-    if let FatFsType::Fat16 = fat_type {
-        // If we have too many clusters even now, this is too big to hold this filesystem type.
-        if number_clusters > 0xFFF5 {
-            return Err(FatError::InvalidPartition);
-        }
-    } else if let FatFsType::Fat12 = fat_type {
-        // If we have too many clusters even now, this is too big to hold this filesystem type.
-        if number_clusters > 0xFF5 {
-            return Err(FatError::InvalidPartition);
-        }
-    } else if let FatFsType::Fat32 = fat_type {
-        // If we have too many clusters even now, this is too big to hold this filesystem type.
-        if number_clusters > 0xFFFFFF5 {
-            return Err(FatError::InvalidPartition);
-        }
-    }
-
-    boot_record.set_media_type(0xf8);
-    boot_record.set_num_heads(heads);
-    boot_record.set_blocks_per_track(blocks_per_track);
-    boot_record.set_blocks_per_cluster(cluster_size);
-    boot_record.set_fats_count(2);
-    boot_record.set_bytes_per_block(512);
-
-    if let FatFsType::Fat32 = fat_type {
-        boot_record.set_root_dir_childs_count(0);
-        boot_record.set_drive_number32(0x80);
-        boot_record.set_volume_label32(*b"NO NAME    ");
-        boot_record.set_reserved_block_count(32);
-        boot_record.set_total_blocks32(block_count as u32);
-
-        let fat_size = (number_clusters * 4 + 8 + Block::LEN_U64 - 1) / Block::LEN_U64;
-        boot_record.set_fat_size32(fat_size as u32);
-
-        // FAT32 specific features
-        boot_record.set_fs_info_block(1);
-        boot_record.set_backup_boot_record_block(6);
-
-        // Make sure to clean the fs info block as it may contains valid data
-        storage_device
-            .write(
-                partition_start + u64::from(boot_record.fs_info_block()) * u64::from(boot_record.bytes_per_block()),
-                &[0x0; 512],
-            )
-            .or(Err(FatError::WriteFailed))?;
-    } else {
-        boot_record.set_root_dir_childs_count(512);
-        boot_record.set_drive_number16(0x80);
-        boot_record.set_volume_label16(*b"NO NAME    ");
-        boot_record.set_reserved_block_count(1);
-        boot_record.set_total_blocks32(block_count as u32);
-
-        let fat_byte_size = if number_clusters > 0xFF5 {
-            number_clusters * 2 + 4
-        } else {
-            (number_clusters * 3 + 1) / 2 + 3
-        };
-
-        let fat_size = (fat_byte_size + Block::LEN_U64 - 1) / Block::LEN_U64;
-        boot_record.set_fat_size16(fat_size as u16);
-    }
-
-    boot_record.set_valid(fat_type);
-
-    assert!(boot_record.is_valid());
-
-    // Init the boot_record as it should be valid now
-    boot_record.initialize_cache();
-    assert!(boot_record.fat_type == fat_type);
-
-    // Write the boot record for FatFilesystem creation
-    storage_device
-        .write(partition_start, &boot_record.data[..])
-        .or(Err(FatError::WriteFailed))?;
-
-    // Now we open the filesystem and clean the FATs while defering initalization.
-    let mut filesystem = parse_fat_boot_record(storage_device, partition_start, partition_size, true)?;
-    table::FatValue::initialize(&filesystem)?;
-
-    // Now that the FATs are clean, we can init the filesystem (and the volume information on FAT32)
-    filesystem.init()?;
-
-    filesystem.create_root_directory()?;
+    format_partition_with_label(
+        storage_device,
+        fat_type,
+        partition_start,
+        partition_size,
+        "",
+    )
+}
 
-    // Rewrite the boot record as it might be updated
-    filesystem.boot_record.flush(&filesystem)?;
+/// Format the partition to hold a given FAT filesystem type with the given volume label.
+/// NOTE: An empty label leave the volume without any label.
+pub fn format_partition_with_label<S: StorageDevice>(
+    storage_device: S,
+    fat_type: FatFsType,
+    partition_start: u64,
+    partition_size: u64,
+    volume_label: &str,
+) -> FatFileSystemResult<()> {
+    let options = FormatOptions::new(fat_type).volume_label(volume_label);
 
-    Ok(())
+    format_partition_with_options(storage_device, partition_start, partition_size, &options)
 }
 
 /// Treat the storage device directly as a partition and try to determine the FAT type of the partition
 pub fn get_fat_type(storage_device: &mut dyn StorageDevice) -> FatFileSystemResult<FatFsType> {
-    Ok(get_fat_boot_record(storage_device, 0)?.fat_type)
+    Ok(get_fat_boot_record(storage_device, 0)?.0.fat_type)
 }
 
 /// Parse the MBR and return an instance to a filesystem at the given partition index.
@@ -684,7 +807,7 @@ pub fn get_partition<S: StorageDevice>(
     let mut storage_device = storage_device;
 
     storage_device
-        .read(index, &mut block)
+        .read(0, &mut block)
         .or(Err(FatError::ReadFailed))?;
 
     if LittleEndian::read_u16(&block[MBR_SIGNATURE..MBR_SIGNATURE + 2]) != 0xAA55 {
@@ -707,7 +830,8 @@ pub fn get_partition<S: StorageDevice>(
     let partition_type: u32 = partition[0x4].into();
 
     match partition_type {
-        0xC => parse_fat_boot_record(
+        // FAT32 (LBA) and exFAT
+        0x7 | 0xC => parse_fat_boot_record(
             storage_device,
             u64::from(partition_start) * block_size as u64,
             u64::from(partition_block_count) * block_size as u64,
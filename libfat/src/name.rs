@@ -39,11 +39,17 @@ pub struct ShortFileNameContext {
     /// The file extension size.
     pub short_name_ext_len: usize,
 
-    /// The count of name conflicting with this name.
-    pub last_index_value: usize,
+    /// Set to true if the 8.3 name cannot represent the VFAT long name without loss.
+    pub is_lossy: bool,
 }
 
 impl ShortFileNameGenerator {
+    /// The count of numeric tails used with the base name before switching to a checksum based base name.
+    pub const BASE_NAME_INDEX_COUNT: usize = 4;
+
+    /// The count of 8.3 names that can be generated for a VFAT long name.
+    pub const MAX_INDEX: usize = 1024;
+
     /// Permite to extract partial part of the VFAT name and format it to 8.3 name.
     fn copy_format_sfn_part(dst: &mut [u8], src: &str, is_base_name: bool) -> (usize, bool, bool) {
         let mut dst_pos = 0;
@@ -89,18 +95,41 @@ impl ShortFileNameGenerator {
         (dst_pos, true, lossy_convertion)
     }
 
-    /// Permite to create a 8.3 name out of a context and a VFAT long file name.
-    pub fn create(context: &mut ShortFileNameContext, lfn: &str) -> ShortFileName {
-        let mut is_lossy = false;
+    /// Write the hexadecimal representation of a checksum in a 4 bytes buffer.
+    fn write_checksum(dst: &mut [u8], mut checksum: u16) {
+        for number in dst.iter_mut() {
+            *number = if checksum % 16 > 9 {
+                (checksum % 16) as u8 + b'A' - 0xA
+            } else {
+                (checksum % 16) as u8 + b'0'
+            };
+
+            checksum >>= 4;
+        }
+    }
+
+    /// Permite to create the 8.3 name at a given index (starting at 1) of the names generated for a VFAT long file name.
+    /// Return None if the index is outside of the range of names that can be generated.
+    /// NOTE: The name at index 1 doesn't have a numeric tail if the 8.3 name represents the VFAT long name without loss.
+    pub fn create(
+        context: &mut ShortFileNameContext,
+        lfn: &str,
+        index: usize,
+    ) -> Option<ShortFileName> {
+        if index == 0 || index > Self::MAX_INDEX {
+            return None;
+        }
+
         if context.short_name_base_len == 0 {
+            let mut is_lossy = false;
             let dot_position = lfn.rfind('.').unwrap_or_else(|| lfn.len());
 
-            let (basename_len, _basename_fits, basename_lossy) = Self::copy_format_sfn_part(
+            let (basename_len, basename_fits, basename_lossy) = Self::copy_format_sfn_part(
                 &mut context.short_name_base,
                 &lfn[..dot_position],
                 true,
             );
-            is_lossy = is_lossy || basename_lossy;
+            is_lossy = is_lossy || basename_lossy || !basename_fits || basename_len == 0;
             context.short_name_base_len = basename_len;
 
             context.short_name_ext_len = 0;
@@ -113,93 +142,85 @@ impl ShortFileNameGenerator {
                     copy_size = 3;
                 }
 
-                let (ext_len, _ext_fits, ext_lossy) = Self::copy_format_sfn_part(
+                let (ext_len, ext_fits, ext_lossy) = Self::copy_format_sfn_part(
                     &mut context.short_name_ext[1..=copy_size],
                     &lfn[dot_position + 1..],
                     false,
                 );
                 context.short_name_ext_len += ext_len;
 
-                is_lossy = is_lossy || ext_lossy;
+                is_lossy = is_lossy || ext_lossy || !ext_fits;
                 if ext_lossy {
                     context.short_name_ext[context.short_name_ext_len - 1] = b'~';
                 }
             }
 
-            if context.short_name_base_len <= 2 {
-                context.checksum = ShortFileName::checksum(&lfn.as_bytes());
-                let mut checksum = context.checksum;
+            context.is_lossy = is_lossy;
 
-                for index in 0..4 {
-                    let number = if checksum % 16 > 9 {
-                        (checksum % 16) as u8 + b'A' - 0xA
-                    } else {
-                        (checksum % 16) as u8 + b'0'
-                    };
+            // Like Windows, very short base names get a part of the checksum to limit collisions.
+            if context.short_name_base_len <= 2 && is_lossy {
+                context.checksum = ShortFileName::checksum(&lfn.as_bytes());
 
-                    context.short_name_base[context.short_name_base_len + index] = number;
-                    checksum >>= 4;
-                }
+                let checksum_start = context.short_name_base_len;
+                Self::write_checksum(
+                    &mut context.short_name_base[checksum_start..checksum_start + 4],
+                    context.checksum,
+                );
 
                 context.short_name_base_len += 4;
                 context.checksum_inserted = true;
             }
         }
 
-        context.last_index_value += 1;
+        let mut short_name_base = context.short_name_base;
+        let mut short_name_base_len = context.short_name_base_len;
+        let mut tail = index;
 
-        if context.last_index_value > 4 && !context.checksum_inserted {
+        // Like Windows, switch to a checksum based base name after a few collisions.
+        if index > Self::BASE_NAME_INDEX_COUNT && !context.checksum_inserted {
             context.checksum = ShortFileName::checksum(&lfn.as_bytes());
-            let mut checksum = context.checksum;
 
-            for index in 2..6 {
-                let number = if checksum % 16 > 9 {
-                    (checksum % 16) as u8 + b'A' - 0xA
-                } else {
-                    (checksum % 16) as u8 + b'0'
-                };
-
-                context.short_name_base[index] = number;
-                checksum >>= 4;
-            }
+            let checksum_start = core::cmp::min(short_name_base_len, 2);
+            Self::write_checksum(
+                &mut short_name_base[checksum_start..checksum_start + 4],
+                context.checksum,
+            );
 
-            context.last_index_value = 1;
-            context.short_name_base_len = 6;
-            context.checksum_inserted = true;
+            short_name_base_len = checksum_start + 4;
+            tail = index - Self::BASE_NAME_INDEX_COUNT;
         }
 
-        let mut index_buffer = [0x0u8; ShortFileName::BASE_FILE_NAME_LEN];
+        // NOTE: MAX_INDEX ensures that the tail always fits in the base name.
+        let mut tail_buffer = [0x0u8; ShortFileName::BASE_FILE_NAME_LEN];
+        let mut tail_len = 0;
+        loop {
+            tail_len += 1;
+            tail_buffer[ShortFileName::BASE_FILE_NAME_LEN - tail_len] = b'0' + (tail % 10) as u8;
+            tail /= 10;
 
-        let mut index = context.last_index_value;
-        let mut index_buffer_len = ShortFileName::BASE_FILE_NAME_LEN;
-        for i in 1..8 {
-            if index == 0 {
-                index_buffer_len = i;
+            if tail == 0 {
                 break;
             }
-
-            index_buffer[8 - i] = b'0' + (index % 10) as u8;
-            index /= 10;
         }
 
-        index_buffer[8 - index_buffer_len] = b'~';
+        tail_len += 1;
+        tail_buffer[ShortFileName::BASE_FILE_NAME_LEN - tail_len] = b'~';
 
         let mut short_name = [0x20u8; ShortFileName::MAX_LEN];
         let mut short_name_len = 0;
-        if context.short_name_base_len != 0 {
-            (&mut short_name[0..context.short_name_base_len])
-                .copy_from_slice(&context.short_name_base[0..context.short_name_base_len]);
-            short_name_len += context.short_name_base_len;
+        if short_name_base_len != 0 {
+            (&mut short_name[0..short_name_base_len])
+                .copy_from_slice(&short_name_base[0..short_name_base_len]);
+            short_name_len += short_name_base_len;
         }
 
-        if is_lossy || context.last_index_value > 1 {
-            let slice = if short_name_len == ShortFileName::BASE_FILE_NAME_LEN {
-                (&mut short_name[short_name_len - index_buffer_len..short_name_len])
-            } else {
-                (&mut short_name[short_name_len..short_name_len + index_buffer_len])
-            };
+        if context.is_lossy || context.checksum_inserted || index > 1 {
+            // The numeric tail replaces the end of the base name if there isn't enough space left.
+            let tail_start =
+                core::cmp::min(short_name_len, ShortFileName::BASE_FILE_NAME_LEN - tail_len);
+            let slice = &mut short_name[tail_start..tail_start + tail_len];
 
-            slice.copy_from_slice(&index_buffer[8 - index_buffer_len..]);
+            slice.copy_from_slice(&tail_buffer[ShortFileName::BASE_FILE_NAME_LEN - tail_len..]);
         }
 
         short_name_len = ShortFileName::BASE_FILE_NAME_LEN;
@@ -209,7 +230,7 @@ impl ShortFileNameGenerator {
                 .copy_from_slice(&context.short_name_ext[1..context.short_name_ext_len]);
         }
 
-        ShortFileName::from_data(&short_name)
+        Some(ShortFileName::from_data(&short_name))
     }
 }
 
@@ -233,9 +254,43 @@ impl ShortFileName {
         }
     }
 
-    /// Import a 8.3 name from a VFAT long name.
-    pub fn from_unformated_str(context: &mut ShortFileNameContext, name: &str) -> Self {
-        ShortFileNameGenerator::create(context, name)
+    /// Import the 8.3 name at a given index of the names generated for a VFAT long name.
+    pub fn from_unformated_str(
+        context: &mut ShortFileNameContext,
+        name: &str,
+        index: usize,
+    ) -> Option<Self> {
+        ShortFileNameGenerator::create(context, name, index)
+    }
+
+    /// Get the value of the numeric tail of a 8.3 name if it has one.
+    pub fn numeric_tail(&self) -> Option<usize> {
+        let base_name = &self.contents[..ShortFileName::BASE_FILE_NAME_LEN];
+        let base_name_len = base_name
+            .iter()
+            .rposition(|c| *c != b' ')
+            .map(|position| position + 1)
+            .unwrap_or(0);
+
+        let tail_position = base_name[..base_name_len]
+            .iter()
+            .rposition(|c| *c == b'~')?;
+        let digits = &base_name[tail_position + 1..base_name_len];
+
+        if digits.is_empty() || digits[0] == b'0' {
+            return None;
+        }
+
+        let mut value = 0;
+        for digit in digits {
+            if !digit.is_ascii_digit() {
+                return None;
+            }
+
+            value = value * 10 + usize::from(digit - b'0');
+        }
+
+        Some(value)
     }
 
     /// Convert a 8.3 name to a Rust char representation.
@@ -282,8 +337,8 @@ impl LongFileName {
     /// The max length of a single LFN entry name.
     pub const MAX_LEN: usize = 13;
 
-    /// The max length of a single LFN entry name when represented as Unicode.
-    pub const MAX_LEN_UNICODE: usize = Self::MAX_LEN * 4;
+    /// The max count of LFN entries used by a single VFAT long name.
+    pub const MAX_ENTRY_COUNT: usize = 20;
 
     /// Import a VFAT long name from a raw FAT directory entry.
     pub fn from_lfn_dir_entry(entry: &LongFileNameDirEntry) -> Self {
@@ -310,12 +365,24 @@ impl LongFileName {
         }
     }
 
-    /// Import a VFAT long name from a Unicode str.
-    pub fn from_utf8(data: &str) -> Self {
-        let mut long_name = [0x0u16; LongFileName::MAX_LEN];
+    /// Import the part of a Unicode str stored in the LFN entry at the given index (starting at 0).
+    /// NOTE: The name is split on UTF-16 code units, a surrogate pair can be stored across two entries.
+    pub fn from_utf8(data: &str, entry_index: usize) -> Self {
+        // Unused characters are padded with 0xFFFF after the null terminator.
+        let mut long_name = [0xFFFFu16; LongFileName::MAX_LEN];
+        let mut len = 0;
+
+        for c in data
+            .encode_utf16()
+            .skip(entry_index * LongFileName::MAX_LEN)
+            .take(LongFileName::MAX_LEN)
+        {
+            long_name[len] = c;
+            len += 1;
+        }
 
-        for (i, c) in data.chars().enumerate().take(LongFileName::MAX_LEN) {
-            c.encode_utf16(&mut long_name[i..]);
+        if len < LongFileName::MAX_LEN {
+            long_name[len] = 0;
         }
 
         LongFileName {
@@ -323,21 +390,22 @@ impl LongFileName {
         }
     }
 
-    /// Convert a VFAT long name to a Rust char representation.
-    pub fn chars(&self) -> Option<[char; Self::MAX_LEN]> {
-        let val = &self.contents;
+    /// Compute the count of LFN entries needed to store a given Unicode str.
+    pub fn entry_count(data: &str) -> u32 {
+        (data.encode_utf16().count() as u32 + LongFileName::MAX_LEN as u32 - 1)
+            / LongFileName::MAX_LEN as u32
+    }
 
-        let mut res: [char; Self::MAX_LEN] = [' '; Self::MAX_LEN];
+    /// Convert a VFAT long name to a Rust char representation.
+    /// NOTE: Invalid UTF-16 sequences (like a surrogate pair split across two entries) are replaced by U+FFFD.
+    pub fn chars(&self) -> [char; Self::MAX_LEN] {
+        let mut res: [char; Self::MAX_LEN] = ['\0'; Self::MAX_LEN];
 
-        for (i, c) in core::char::decode_utf16(val.iter().cloned()).enumerate() {
-            if let Ok(c) = c {
-                res[i] = c;
-            } else {
-                return None;
-            }
+        for (i, c) in core::char::decode_utf16(self.contents.iter().cloned()).enumerate() {
+            res[i] = c.unwrap_or(core::char::REPLACEMENT_CHARACTER);
         }
 
-        Some(res)
+        res
     }
 
     /// Return the raw content of a VFAT long name.
@@ -345,3 +413,122 @@ impl LongFileName {
         self.contents
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{LongFileName, ShortFileName, ShortFileNameContext, ShortFileNameGenerator};
+
+    /// Generate the 8.3 name at a given index for a given VFAT long name.
+    fn generate(name: &str, index: usize) -> Option<[u8; ShortFileName::MAX_LEN]> {
+        let mut context = ShortFileNameContext::default();
+        ShortFileName::from_unformated_str(&mut context, name, index)
+            .map(|short_name| short_name.as_bytes())
+    }
+
+    #[test]
+    fn lossless_name_tail() {
+        assert_eq!(generate("readme.txt", 1), Some(*b"README  TXT"));
+        assert_eq!(generate("readme.txt", 2), Some(*b"README~2TXT"));
+        assert_eq!(generate("abcdefgh.txt", 2), Some(*b"ABCDEF~2TXT"));
+    }
+
+    #[test]
+    fn lossy_name_tail() {
+        assert_eq!(generate("Long File Name.text", 1), Some(*b"LONGFI~1TEX"));
+        assert_eq!(generate("Long File Name.text", 4), Some(*b"LONGFI~4TEX"));
+
+        // After a few collisions, the base name is replaced by a checksum based one.
+        let short_name = generate("Long File Name.text", 5).unwrap();
+        assert_eq!(&short_name[..2], b"LO");
+        assert_eq!(&short_name[6..], b"~1TEX");
+        assert_ne!(&short_name[2..6], b"NGFI");
+
+        let short_name = generate("Long File Name.text", 14).unwrap();
+        assert_eq!(&short_name[..2], b"LO");
+        assert_eq!(&short_name[5..], b"~10TEX");
+    }
+
+    #[test]
+    fn tail_range() {
+        assert!(generate("Long File Name.text", 0).is_none());
+        assert!(generate("Long File Name.text", ShortFileNameGenerator::MAX_INDEX + 1).is_none());
+
+        let mut context = ShortFileNameContext::default();
+        let mut names = [[0x0u8; ShortFileName::MAX_LEN]; ShortFileNameGenerator::MAX_INDEX];
+
+        for (index, name) in names.iter_mut().enumerate() {
+            *name =
+                ShortFileName::from_unformated_str(&mut context, "Long File Name.text", index + 1)
+                    .unwrap()
+                    .as_bytes();
+        }
+
+        // Every index must give a different name.
+        for (index, name) in names.iter().enumerate() {
+            assert!(names[index + 1..].iter().all(|other| other != name));
+        }
+    }
+
+    #[test]
+    fn numeric_tail() {
+        let tail = |data: &[u8]| ShortFileName::from_data(data).numeric_tail();
+
+        assert_eq!(tail(b"README  TXT"), None);
+        assert_eq!(tail(b"README~2TXT"), Some(2));
+        assert_eq!(tail(b"LO1F~123TXT"), Some(123));
+        assert_eq!(tail(b"FOO~    TXT"), None);
+        assert_eq!(tail(b"FOO~0   TXT"), None);
+        assert_eq!(tail(b"FOO~1A  TXT"), None);
+
+        // The tail value gives the index of the generated name.
+        for index in 2..=ShortFileNameGenerator::MAX_INDEX {
+            let short_name =
+                ShortFileName::from_data(&generate("Long File Name.text", index).unwrap());
+            let tail = short_name.numeric_tail().unwrap();
+
+            assert!(tail == index || tail + ShortFileNameGenerator::BASE_NAME_INDEX_COUNT == index);
+        }
+    }
+
+    #[test]
+    fn long_name_entry_count() {
+        assert_eq!(LongFileName::entry_count("a"), 1);
+        assert_eq!(LongFileName::entry_count("abcdefghijklm"), 1);
+        assert_eq!(LongFileName::entry_count("abcdefghijklmn"), 2);
+
+        // Multibyte UTF-8 characters are a single UTF-16 code unit.
+        assert_eq!(LongFileName::entry_count("ééééééééééééé"), 1);
+
+        // Characters outside of the BMP are a surrogate pair.
+        assert_eq!(LongFileName::entry_count("abcdefghijk\u{1F600}"), 1);
+        assert_eq!(LongFileName::entry_count("abcdefghijkl\u{1F600}"), 2);
+    }
+
+    #[test]
+    fn long_name_surrogate_pair() {
+        let name = "abcdefghijkl\u{1F600}é";
+
+        // The surrogate pair is split across the two entries.
+        let first = LongFileName::from_utf8(name, 0).as_contents();
+        assert_eq!(first[11], u16::from(b'l'));
+        assert_eq!(first[12], 0xD83D);
+
+        let second = LongFileName::from_utf8(name, 1).as_contents();
+        assert_eq!(second[0], 0xDE00);
+        assert_eq!(second[1], 0xE9);
+        assert_eq!(second[2], 0);
+        assert!(second[3..].iter().all(|c| *c == 0xFFFF));
+
+        // A full entry doesn't have a null terminator.
+        let full = LongFileName::from_utf8("abcdefghijk\u{1F600}", 0).as_contents();
+        assert_eq!(full[11], 0xD83D);
+        assert_eq!(full[12], 0xDE00);
+
+        // Characters are decoded back from the UTF-16 code units.
+        let chars = LongFileName::from_utf8("a\u{1F600}é", 0).chars();
+        assert_eq!(chars[0], 'a');
+        assert_eq!(chars[1], '\u{1F600}');
+        assert_eq!(chars[2], 'é');
+        assert_eq!(chars[3], '\0');
+    }
+}
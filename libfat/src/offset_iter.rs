@@ -28,24 +28,31 @@ impl ClusterOffsetIter {
         fs: &FatFileSystem<S>,
         cluster: Cluster,
         start_cluster_offset: Option<u64>,
+    ) -> Self {
+        Self::from_cluster_iter(fs, FatClusterIter::new(fs, cluster), start_cluster_offset)
+    }
+
+    /// Create a new iterator from a cluster iterator and a block index.
+    pub fn from_cluster_iter<S: StorageDevice>(
+        fs: &FatFileSystem<S>,
+        mut cluster_iter: FatClusterIter,
+        start_cluster_offset: Option<u64>,
     ) -> Self {
         let blocks_per_cluster = u64::from(fs.boot_record.blocks_per_cluster());
 
-        let (cluster, start_cluster_offset) =
-            if let Some(start_cluster_offset) = start_cluster_offset {
-                let cluster_offset = start_cluster_offset / blocks_per_cluster;
-                let start_cluster_offset = start_cluster_offset % blocks_per_cluster;
-                (
-                    Cluster(cluster.0 + cluster_offset as u32),
-                    Some(start_cluster_offset),
-                )
-            } else {
-                (cluster, start_cluster_offset)
-            };
+        let start_cluster_offset = if let Some(start_cluster_offset) = start_cluster_offset {
+            // Skip the clusters before the requested block by following the chain.
+            for _ in 0..start_cluster_offset / blocks_per_cluster {
+                cluster_iter.next(fs);
+            }
+            Some(start_cluster_offset % blocks_per_cluster)
+        } else {
+            start_cluster_offset
+        };
 
         ClusterOffsetIter {
             counter: blocks_per_cluster as usize,
-            cluster_iter: FatClusterIter::new(fs, cluster),
+            cluster_iter,
             start_cluster_offset,
             last_cluster: None,
         }
@@ -32,6 +32,9 @@ pub struct FatClusterIter {
 
     /// The last FatValue used.
     last_fat: Option<FatValue>,
+
+    /// The count of clusters left when the chain isn't described by the FAT (exFAT "NoFatChain").
+    contiguous_left: Option<u32>,
 }
 
 impl FatClusterIter {
@@ -41,6 +44,21 @@ impl FatClusterIter {
         FatClusterIter {
             current_cluster: Some(cluster),
             last_fat: fat_value,
+            contiguous_left: None,
+        }
+    }
+
+    /// Create a new Cluster iterator over ``cluster_count`` contiguous clusters starting at ``cluster``.
+    /// This doesn't rely on the FAT and is used for exFAT "NoFatChain" cluster chains.
+    pub fn new_contiguous(cluster: Cluster, cluster_count: u32) -> FatClusterIter {
+        FatClusterIter {
+            current_cluster: if cluster_count == 0 {
+                None
+            } else {
+                Some(cluster)
+            },
+            last_fat: None,
+            contiguous_left: Some(cluster_count.saturating_sub(1)),
         }
     }
 }
@@ -50,6 +68,17 @@ impl<S: StorageDevice> FileSystemIterator<S> for FatClusterIter {
     fn next(&mut self, filesystem: &FatFileSystem<S>) -> Option<Cluster> {
         let res = self.current_cluster?;
 
+        if let Some(contiguous_left) = self.contiguous_left {
+            if contiguous_left == 0 {
+                self.current_cluster = None;
+            } else {
+                self.current_cluster = Some(Cluster(res.0 + 1));
+                self.contiguous_left = Some(contiguous_left - 1);
+            }
+
+            return Some(res);
+        }
+
         match self.last_fat {
             Some(FatValue::Data(data)) => {
                 self.current_cluster = Some(Cluster(data));
@@ -83,6 +112,26 @@ impl FatValue {
         }
     }
 
+    /// Create a ``FatValue`` from a raw exFAT value.
+    fn from_exfat_value(val: u32) -> Self {
+        match val {
+            0 => FatValue::Free,
+            0xFFFF_FFF7 => FatValue::Bad,
+            0xFFFF_FFF8..=0xFFFF_FFFF => FatValue::EndOfChain,
+            n => FatValue::Data(n as u32),
+        }
+    }
+
+    /// Convert a ```FatValue``` to a raw exFAT value.
+    fn to_exfat_value(self) -> u32 {
+        match self {
+            FatValue::Free => 0,
+            FatValue::Bad => 0xFFFF_FFF7,
+            FatValue::EndOfChain => 0xFFFF_FFFF,
+            FatValue::Data(n) => n,
+        }
+    }
+
     /// Create a ``FatValue`` from a raw FAT16 value.
     fn from_fat16_value(val: u16) -> Self {
         match val {
@@ -123,54 +172,14 @@ impl FatValue {
         }
     }
 
-    /// Create a ```FatValue``` from a raw cluster.
-    /// Used internally in get and raw_put.
-    fn from_cluster<S: StorageDevice>(
-        fs: &FatFileSystem<S>,
-        cluster: Cluster,
-        fat_index: u32,
-    ) -> FatFileSystemResult<(Self, u64)> {
-        let fat_offset = cluster.to_fat_offset(fs.boot_record.fat_type);
-        let cluster_storage_offset = cluster.to_fat_bytes_offset(fs)
-            + u64::from(fat_index * fs.boot_record.fat_size())
-                * u64::from(fs.boot_record.bytes_per_block());
-
-        let cluster_offset = u64::from(fat_offset % u32::from(fs.boot_record.bytes_per_block()));
-        let partition_storage_offset = fs.partition_start + cluster_storage_offset + cluster_offset;
-
-        match fs.boot_record.fat_type {
-            FatFsType::Fat32 => {
-                let mut data = [0x0u8; 4];
-                fs.storage_device
-                    .lock()
-                    .read(partition_storage_offset, &mut data)
-                    .or(Err(FatError::ReadFailed))?;
-
-                Ok((
-                    Self::from_fat32_value(LittleEndian::read_u32(&data) & 0x0FFF_FFFF),
-                    cluster_storage_offset,
-                ))
-            }
-            FatFsType::Fat16 => {
-                let mut data = [0x0u8; 2];
-                fs.storage_device
-                    .lock()
-                    .read(partition_storage_offset, &mut data)
-                    .or(Err(FatError::ReadFailed))?;
-
-                Ok((
-                    Self::from_fat16_value(LittleEndian::read_u16(&data)),
-                    cluster_storage_offset,
-                ))
-            }
+    /// Create a ```FatValue``` from the raw FAT entry of a given cluster.
+    /// NOTE: ``data`` must hold at least 2 bytes on FAT12/FAT16 and 4 bytes on FAT32/exFAT.
+    fn from_raw(fat_type: FatFsType, cluster: Cluster, data: &[u8]) -> Self {
+        match fat_type {
+            FatFsType::Fat32 => Self::from_fat32_value(LittleEndian::read_u32(data) & 0x0FFF_FFFF),
+            FatFsType::Fat16 => Self::from_fat16_value(LittleEndian::read_u16(data)),
             FatFsType::Fat12 => {
-                let mut data = [0x0u8; 2];
-                fs.storage_device
-                    .lock()
-                    .read(partition_storage_offset, &mut data)
-                    .or(Err(FatError::ReadFailed))?;
-
-                let mut value = LittleEndian::read_u16(&data);
+                let mut value = LittleEndian::read_u16(data);
 
                 value = if (cluster.0 & 1) == 1 {
                     value >> 4
@@ -178,63 +187,86 @@ impl FatValue {
                     value & 0x0FFF
                 };
 
-                Ok((Self::from_fat12_value(value), cluster_storage_offset))
+                Self::from_fat12_value(value)
             }
-            _ => unimplemented!(),
+            FatFsType::ExFat => Self::from_exfat_value(LittleEndian::read_u32(data)),
+        }
+    }
+
+    /// Get the size in bytes of the raw FAT entry read to decode a ``FatValue``.
+    fn raw_size(fat_type: FatFsType) -> usize {
+        match fat_type {
+            FatFsType::Fat12 | FatFsType::Fat16 => 2,
+            FatFsType::Fat32 | FatFsType::ExFat => 4,
         }
     }
 
+    /// Create a ```FatValue``` from a raw cluster.
+    /// Used internally in get and put.
+    fn from_cluster<S: StorageDevice>(
+        fs: &FatFileSystem<S>,
+        cluster: Cluster,
+    ) -> FatFileSystemResult<Self> {
+        let fat_type = fs.boot_record.fat_type;
+        let fat_offset = u64::from(cluster.to_fat_offset(fat_type));
+
+        let mut data = [0x0u8; 4];
+        fs.fat_cache
+            .lock()
+            .read(fs, fat_offset, &mut data[..Self::raw_size(fat_type)])?;
+
+        Ok(Self::from_raw(fat_type, cluster, &data))
+    }
+
     /// Get the ```FatValue``` of a given cluster.
     pub fn get<S: StorageDevice>(
         fs: &FatFileSystem<S>,
         cluster: Cluster,
     ) -> FatFileSystemResult<FatValue> {
-        Ok(FatValue::from_cluster(fs, cluster, 0)?.0)
+        FatValue::from_cluster(fs, cluster)
     }
 
-    /// Write the given ``FatValue``at a given ``Cluster`` in one FAT.
-    fn raw_put<S: StorageDevice>(
+    /// Write the given ``FatValue``at a given ``Cluster`` in all FATs.
+    /// NOTE: The FATs are only updated on the storage device when the FAT cache is flushed.
+    pub fn put<S: StorageDevice>(
         fs: &FatFileSystem<S>,
         cluster: Cluster,
         value: FatValue,
-        fat_index: u32,
     ) -> FatFileSystemResult<()> {
-        let (res, cluster_storage_offset) = FatValue::from_cluster(fs, cluster, fat_index)?;
-
-        let fat_offset = cluster.to_fat_offset(fs.boot_record.fat_type);
-        let cluster_offset = u64::from(fat_offset % u32::from(fs.boot_record.bytes_per_block()));
-        let partition_storage_offset = fs.partition_start + cluster_storage_offset + cluster_offset;
-
         // no write needed
-        if res == value {
+        if FatValue::from_cluster(fs, cluster)? == value {
             return Ok(());
         }
 
+        // The volume must be marked as dirty before any change reaches the storage device
+        fs.mark_dirty()?;
+
+        let fat_offset = u64::from(cluster.to_fat_offset(fs.boot_record.fat_type));
+        let mut fat_cache = fs.fat_cache.lock();
+
         match fs.boot_record.fat_type {
             FatFsType::Fat32 => {
                 let mut data = [0x0u8; 4];
-                LittleEndian::write_u32(&mut data, value.to_fat32_value() & 0x0FFF_FFFF);
-                fs.storage_device
-                    .lock()
-                    .write(partition_storage_offset, &data)
-                    .or(Err(FatError::WriteFailed))?;
+
+                // The 4 high bits are reserved and must be preserved
+                fat_cache.read(fs, fat_offset, &mut data)?;
+                let reserved_bits = LittleEndian::read_u32(&data) & 0xF000_0000;
+
+                LittleEndian::write_u32(
+                    &mut data,
+                    (value.to_fat32_value() & 0x0FFF_FFFF) | reserved_bits,
+                );
+                fat_cache.write(fs, fat_offset, &data)?;
             }
             FatFsType::Fat16 => {
                 let mut data = [0x0u8; 2];
                 LittleEndian::write_u16(&mut data, value.to_fat16_value());
-                fs.storage_device
-                    .lock()
-                    .write(partition_storage_offset, &data)
-                    .or(Err(FatError::WriteFailed))?;
+                fat_cache.write(fs, fat_offset, &data)?;
             }
             FatFsType::Fat12 => {
                 // Welcome to the IBM world
                 let mut data = [0x0u8; 2];
-
-                fs.storage_device
-                    .lock()
-                    .read(partition_storage_offset, &mut data)
-                    .or(Err(FatError::ReadFailed))?;
+                fat_cache.read(fs, fat_offset, &mut data)?;
 
                 let value = value.to_fat12_value();
 
@@ -243,41 +275,454 @@ impl FatValue {
                     data[1] = (value >> 4) as u8;
                 } else {
                     data[0] = value as u8;
-                    data[1] = (data[0] & 0xF0) | ((value >> 8) & 0x0F) as u8;
+                    data[1] = (data[1] & 0xF0) | ((value >> 8) & 0x0F) as u8;
+                }
+
+                fat_cache.write(fs, fat_offset, &data)?;
+            }
+            FatFsType::ExFat => {
+                let mut data = [0x0u8; 4];
+                LittleEndian::write_u32(&mut data, value.to_exfat_value());
+                fat_cache.write(fs, fat_offset, &data)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read the raw value of the second entry of the FAT, holding the volume status bits.
+    /// NOTE: Only FAT16 and FAT32 keep status bits in this entry.
+    pub(crate) fn get_volume_status<S: StorageDevice>(
+        fs: &FatFileSystem<S>,
+    ) -> FatFileSystemResult<u32> {
+        let fat_offset = u64::from(Cluster(1).to_fat_offset(fs.boot_record.fat_type));
+        let mut fat_cache = fs.fat_cache.lock();
+
+        match fs.boot_record.fat_type {
+            FatFsType::Fat32 => {
+                let mut data = [0x0u8; 4];
+                fat_cache.read(fs, fat_offset, &mut data)?;
+                Ok(LittleEndian::read_u32(&data))
+            }
+            FatFsType::Fat16 => {
+                let mut data = [0x0u8; 2];
+                fat_cache.read(fs, fat_offset, &mut data)?;
+                Ok(u32::from(LittleEndian::read_u16(&data)))
+            }
+            _ => Err(FatError::Custom {
+                name: "no volume status on this filesystem",
+            }),
+        }
+    }
+
+    /// Write the raw value of the second entry of the FAT, holding the volume status bits.
+    /// NOTE: Only FAT16 and FAT32 keep status bits in this entry.
+    pub(crate) fn put_volume_status<S: StorageDevice>(
+        fs: &FatFileSystem<S>,
+        value: u32,
+    ) -> FatFileSystemResult<()> {
+        let fat_offset = u64::from(Cluster(1).to_fat_offset(fs.boot_record.fat_type));
+        let mut fat_cache = fs.fat_cache.lock();
+
+        match fs.boot_record.fat_type {
+            FatFsType::Fat32 => {
+                let mut data = [0x0u8; 4];
+                LittleEndian::write_u32(&mut data, value);
+                fat_cache.write(fs, fat_offset, &data)
+            }
+            FatFsType::Fat16 => {
+                let mut data = [0x0u8; 2];
+                LittleEndian::write_u16(&mut data, value as u16);
+                fat_cache.write(fs, fat_offset, &data)
+            }
+            _ => Err(FatError::Custom {
+                name: "no volume status on this filesystem",
+            }),
+        }
+    }
+
+    /// Initialize clean FATs.
+    /// NOTE: The whole FATs are zeroed, including the reserved bits of FAT32 entries.
+    pub(crate) fn initialize<S: StorageDevice>(fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        // The blocks kept in memory would hide the zeroed FATs.
+        fs.fat_cache.lock().invalidate(fs)?;
+
+        let fat_len =
+            u64::from(fs.boot_record.fat_size()) * u64::from(fs.boot_record.bytes_per_block());
+        let block = [0x0u8; crate::MINIMAL_BLOCK_SIZE];
+
+        let mut storage_device = fs.storage_device.lock();
+
+        for fat_index in 0..u32::from(fs.boot_record.fats_count()) {
+            let fat_start = get_fat_storage_offset(fs, fat_index);
+
+            let mut offset = 0;
+            while offset < fat_len {
+                storage_device
+                    .write(fat_start + offset, &block)
+                    .or(Err(FatError::WriteFailed))?;
+
+                offset += block.len() as u64;
+            }
+        }
+
+        drop(storage_device);
+
+        // The first entry holds the media descriptor and the second one is an end of chain marker.
+        let media_type = u32::from(fs.boot_record.media_type());
+        let first_entry_value = match fs.boot_record.fat_type {
+            FatFsType::Fat12 => 0xF00 | media_type,
+            FatFsType::Fat16 => 0xFF00 | media_type,
+            _ => 0x0FFF_FF00 | media_type,
+        };
+
+        Self::put(fs, Cluster(0), FatValue::Data(first_entry_value))?;
+        Self::put(fs, Cluster(1), FatValue::EndOfChain)
+    }
+}
+
+/// The count of FAT blocks kept in the FAT cache.
+const FAT_CACHE_ENTRY_COUNT: usize = 16;
+
+#[derive(Clone, Copy)]
+/// Represent a block of the FAT kept in memory.
+struct FatCacheEntry {
+    /// The index of the block inside the FAT. (None if the entry is unused)
+    block_index: Option<u64>,
+
+    /// Set when the block was modified and need to be written back to the FATs.
+    is_dirty: bool,
+
+    /// The value of the access counter when the block was last used.
+    last_access: u64,
+
+    /// The content of the block.
+    data: [u8; crate::MINIMAL_BLOCK_SIZE],
+}
+
+/// Represent a write-back cache of the FAT blocks.
+/// Modifications are written to all the FATs when a block is evicted or when the cache is flushed.
+pub(crate) struct FatCache {
+    /// The blocks kept in memory.
+    entries: [FatCacheEntry; FAT_CACHE_ENTRY_COUNT],
+
+    /// Counter used to find the least recently used block.
+    access_counter: u64,
+}
+
+impl FatCache {
+    /// Create a new empty FAT cache.
+    pub fn new() -> Self {
+        FatCache {
+            entries: [FatCacheEntry {
+                block_index: None,
+                is_dirty: false,
+                last_access: 0,
+                data: [0x0; crate::MINIMAL_BLOCK_SIZE],
+            }; FAT_CACHE_ENTRY_COUNT],
+            access_counter: 0,
+        }
+    }
+
+    /// Compute the offset on the storage device of a block of a given FAT.
+    fn get_storage_offset<S: StorageDevice>(
+        fs: &FatFileSystem<S>,
+        fat_index: u32,
+        block_index: u64,
+    ) -> u64 {
+        get_fat_storage_offset(fs, fat_index) + block_index * crate::MINIMAL_BLOCK_SIZE as u64
+    }
+
+    /// Get the entry holding a given block of the FAT, loading it if needed.
+    fn get_entry<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        block_index: u64,
+    ) -> FatFileSystemResult<&mut FatCacheEntry> {
+        self.access_counter += 1;
+
+        let entry_index = match self
+            .entries
+            .iter()
+            .position(|entry| entry.block_index == Some(block_index))
+        {
+            Some(entry_index) => entry_index,
+            None => {
+                // Evict the least recently used block
+                let mut entry_index = 0;
+                for (index, entry) in self.entries.iter().enumerate() {
+                    if entry.last_access < self.entries[entry_index].last_access {
+                        entry_index = index;
+                    }
                 }
 
+                self.flush_entry(fs, entry_index)?;
+
+                let entry = &mut self.entries[entry_index];
+                entry.block_index = None;
+
                 fs.storage_device
                     .lock()
-                    .write(partition_storage_offset, &data)
-                    .or(Err(FatError::WriteFailed))?;
+                    .read(
+                        Self::get_storage_offset(fs, u32::from(fs.active_fat), block_index),
+                        &mut entry.data,
+                    )
+                    .or(Err(FatError::ReadFailed))?;
+
+                entry.block_index = Some(block_index);
+                entry_index
+            }
+        };
+
+        let entry = &mut self.entries[entry_index];
+        entry.last_access = self.access_counter;
+
+        Ok(entry)
+    }
+
+    /// Write back a modified block to all the FATs (or only to the active FAT when the mirroring is disabled).
+    fn flush_entry<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        entry_index: usize,
+    ) -> FatFileSystemResult<()> {
+        let entry = &mut self.entries[entry_index];
+
+        let fat_indexes = if fs.boot_record.is_fat_mirroring_enabled() {
+            0..u32::from(fs.boot_record.fats_count())
+        } else {
+            u32::from(fs.active_fat)..u32::from(fs.active_fat) + 1
+        };
+
+        if let Some(block_index) = entry.block_index {
+            if entry.is_dirty {
+                let mut storage_device = fs.storage_device.lock();
+
+                for fat_index in fat_indexes {
+                    storage_device
+                        .write(
+                            Self::get_storage_offset(fs, fat_index, block_index),
+                            &entry.data,
+                        )
+                        .or(Err(FatError::WriteFailed))?;
+                }
+
+                entry.is_dirty = false;
             }
-            _ => unimplemented!(),
         }
 
         Ok(())
     }
 
-    /// Write the given ``FatValue``at a given ``Cluster`` in all FATs.
-    pub fn put<S: StorageDevice>(
+    /// Read the FAT at a given byte offset into a given buffer.
+    pub fn read<S: StorageDevice>(
+        &mut self,
         fs: &FatFileSystem<S>,
-        cluster: Cluster,
-        value: FatValue,
+        offset: u64,
+        buf: &mut [u8],
     ) -> FatFileSystemResult<()> {
-        for fat_index in 0..u32::from(fs.boot_record.fats_count()) {
-            Self::raw_put(fs, cluster, value, fat_index)?;
+        for (index, value) in buf.iter_mut().enumerate() {
+            let offset = offset + index as u64;
+            let entry = self.get_entry(fs, offset / crate::MINIMAL_BLOCK_SIZE as u64)?;
+
+            *value = entry.data[(offset % crate::MINIMAL_BLOCK_SIZE as u64) as usize];
         }
+
         Ok(())
     }
 
-    /// Initialize clean FATs.
-    pub(crate) fn initialize<S: StorageDevice>(fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
-        for i in 0..fs.boot_record.cluster_count {
-            Self::put(fs, Cluster(i), FatValue::Free)?;
+    /// Write the given buffer in the FAT at a given byte offset.
+    pub fn write<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+        offset: u64,
+        buf: &[u8],
+    ) -> FatFileSystemResult<()> {
+        for (index, value) in buf.iter().enumerate() {
+            let offset = offset + index as u64;
+            let entry = self.get_entry(fs, offset / crate::MINIMAL_BLOCK_SIZE as u64)?;
+
+            entry.data[(offset % crate::MINIMAL_BLOCK_SIZE as u64) as usize] = *value;
+            entry.is_dirty = true;
+        }
+
+        Ok(())
+    }
+
+    /// Write back all the modified blocks to the FATs.
+    pub fn flush<S: StorageDevice>(&mut self, fs: &FatFileSystem<S>) -> FatFileSystemResult<()> {
+        for entry_index in 0..self.entries.len() {
+            self.flush_entry(fs, entry_index)?;
         }
+
+        Ok(())
+    }
+
+    /// Write back all the modified blocks and drop all the blocks kept in memory.
+    /// Used when the FAT content changed on the storage device.
+    pub fn invalidate<S: StorageDevice>(
+        &mut self,
+        fs: &FatFileSystem<S>,
+    ) -> FatFileSystemResult<()> {
+        self.flush(fs)?;
+
+        for entry in self.entries.iter_mut() {
+            entry.block_index = None;
+        }
+
         Ok(())
     }
 }
 
+/// Compute the offset on the storage device of a given FAT.
+pub(crate) fn get_fat_storage_offset<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    fat_index: u32,
+) -> u64 {
+    let fat_start_block = u64::from(fs.boot_record.fat_start_block())
+        + u64::from(fat_index) * u64::from(fs.boot_record.fat_size());
+
+    fs.partition_start + fat_start_block * u64::from(fs.boot_record.bytes_per_block())
+}
+
+/// Read the raw value of a cluster entry in a given FAT directly from the storage device.
+/// NOTE: The FAT cache isn't used, it needs to be flushed first to get up to date values.
+pub(crate) fn read_raw_fat_entry<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    fat_index: u32,
+    cluster: Cluster,
+) -> FatFileSystemResult<u32> {
+    let offset = get_fat_storage_offset(fs, fat_index)
+        + u64::from(cluster.to_fat_offset(fs.boot_record.fat_type));
+
+    let mut data = [0x0u8; 4];
+    let data = match fs.boot_record.fat_type {
+        FatFsType::Fat12 | FatFsType::Fat16 => &mut data[..2],
+        FatFsType::Fat32 | FatFsType::ExFat => &mut data[..],
+    };
+
+    fs.storage_device
+        .lock()
+        .read(offset, data)
+        .or(Err(FatError::ReadFailed))?;
+
+    let value = match fs.boot_record.fat_type {
+        FatFsType::Fat32 => LittleEndian::read_u32(data) & 0x0FFF_FFFF,
+        FatFsType::ExFat => LittleEndian::read_u32(data),
+        FatFsType::Fat16 => u32::from(LittleEndian::read_u16(data)),
+        FatFsType::Fat12 => {
+            let value = LittleEndian::read_u16(data);
+
+            if (cluster.0 & 1) == 1 {
+                u32::from(value >> 4)
+            } else {
+                u32::from(value & 0x0FFF)
+            }
+        }
+    };
+
+    Ok(value)
+}
+
+/// Compare a FAT with a reference FAT directly on the storage device.
+/// ``report`` is called with the cluster, the reference value and the value of every differing entry.
+/// Return the count of differing entries.
+pub(crate) fn compare_fat<S: StorageDevice, F: FnMut(Cluster, u32, u32)>(
+    fs: &FatFileSystem<S>,
+    reference_fat_index: u32,
+    fat_index: u32,
+    mut report: F,
+) -> FatFileSystemResult<u32> {
+    let reference_start = get_fat_storage_offset(fs, reference_fat_index);
+    let fat_start = get_fat_storage_offset(fs, fat_index);
+    let fat_len =
+        u64::from(fs.boot_record.fat_size()) * u64::from(fs.boot_record.bytes_per_block());
+
+    let mut reference_block = [0x0u8; crate::MINIMAL_BLOCK_SIZE];
+    let mut block = [0x0u8; crate::MINIMAL_BLOCK_SIZE];
+
+    let mut mismatch_count = 0;
+    let mut next_cluster = 0;
+    let mut offset = 0;
+
+    while offset < fat_len {
+        {
+            let mut storage_device = fs.storage_device.lock();
+
+            storage_device
+                .read(reference_start + offset, &mut reference_block)
+                .or(Err(FatError::ReadFailed))?;
+            storage_device
+                .read(fat_start + offset, &mut block)
+                .or(Err(FatError::ReadFailed))?;
+        }
+
+        let block_end = offset + crate::MINIMAL_BLOCK_SIZE as u64;
+
+        if reference_block[..] != block[..] {
+            // Compare all the entries stored (even partially) in this block.
+            while next_cluster < fs.boot_record.cluster_count
+                && u64::from(Cluster(next_cluster).to_fat_offset(fs.boot_record.fat_type))
+                    < block_end
+            {
+                let cluster = Cluster(next_cluster);
+                let reference_value = read_raw_fat_entry(fs, reference_fat_index, cluster)?;
+                let value = read_raw_fat_entry(fs, fat_index, cluster)?;
+
+                if reference_value != value {
+                    mismatch_count += 1;
+                    report(cluster, reference_value, value);
+                }
+
+                next_cluster += 1;
+            }
+        } else {
+            // Skip the entries fully stored in this block.
+            while next_cluster < fs.boot_record.cluster_count
+                && u64::from(Cluster(next_cluster + 1).to_fat_offset(fs.boot_record.fat_type))
+                    <= block_end
+            {
+                next_cluster += 1;
+            }
+        }
+
+        offset = block_end;
+    }
+
+    Ok(mismatch_count)
+}
+
+/// Copy a FAT over another one directly on the storage device.
+/// NOTE: The FAT cache isn't used, it needs to be flushed first and invalidated after.
+pub(crate) fn copy_fat<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    source_fat_index: u32,
+    destination_fat_index: u32,
+) -> FatFileSystemResult<()> {
+    let source_start = get_fat_storage_offset(fs, source_fat_index);
+    let destination_start = get_fat_storage_offset(fs, destination_fat_index);
+    let fat_len =
+        u64::from(fs.boot_record.fat_size()) * u64::from(fs.boot_record.bytes_per_block());
+
+    let mut block = [0x0u8; crate::MINIMAL_BLOCK_SIZE];
+
+    let mut offset = 0;
+    while offset < fat_len {
+        let mut storage_device = fs.storage_device.lock();
+
+        storage_device
+            .read(source_start + offset, &mut block)
+            .or(Err(FatError::ReadFailed))?;
+        storage_device
+            .write(destination_start + offset, &block)
+            .or(Err(FatError::WriteFailed))?;
+
+        offset += crate::MINIMAL_BLOCK_SIZE as u64;
+    }
+
+    Ok(())
+}
+
 /// Get the last cluster of a cluster chain.
 pub fn get_last_cluster<S: StorageDevice>(
     fs: &FatFileSystem<S>,
@@ -302,18 +747,101 @@ pub fn get_last_and_previous_cluster<S: StorageDevice>(
     Ok((current_cluster, previous_cluster))
 }
 
+/// Link ``cluster_count`` contiguous clusters starting at ``cluster`` in the FATs.
+/// Used to convert exFAT "NoFatChain" cluster chains to regular ones.
+pub(crate) fn link_contiguous_clusters<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    cluster: Cluster,
+    cluster_count: u32,
+) -> FatFileSystemResult<()> {
+    for index in 0..cluster_count {
+        let current_cluster = Cluster(cluster.0 + index);
+        let value = if index + 1 == cluster_count {
+            FatValue::EndOfChain
+        } else {
+            FatValue::Data(current_cluster.0 + 1)
+        };
+
+        FatValue::put(fs, current_cluster, value)?;
+    }
+
+    Ok(())
+}
+
+/// Find the first cluster of the ``[cluster, end_cluster)`` range that is free (or used when ``free`` isn't set).
+/// NOTE: The FAT is scanned a block at a time, the FAT cache is only locked once.
+pub(crate) fn find_cluster<S: StorageDevice>(
+    fs: &FatFileSystem<S>,
+    cluster: Cluster,
+    end_cluster: u32,
+    free: bool,
+) -> FatFileSystemResult<Option<Cluster>> {
+    let fat_type = fs.boot_record.fat_type;
+    let raw_size = FatValue::raw_size(fat_type);
+    let block_size = crate::MINIMAL_BLOCK_SIZE as u64;
+
+    let mut fat_cache = fs.fat_cache.lock();
+
+    // One more byte is kept after the block for FAT12 entries crossing a block boundary.
+    let mut block = [0x0u8; crate::MINIMAL_BLOCK_SIZE + 1];
+    let mut loaded_block_index = None;
+
+    for index in cluster.0..end_cluster {
+        let fat_offset = u64::from(Cluster(index).to_fat_offset(fat_type));
+        let block_index = fat_offset / block_size;
+        let block_offset = (fat_offset % block_size) as usize;
+
+        if loaded_block_index != Some(block_index) {
+            let entry = fat_cache.get_entry(fs, block_index)?;
+            block[..crate::MINIMAL_BLOCK_SIZE].copy_from_slice(&entry.data);
+            loaded_block_index = Some(block_index);
+
+            if fat_type == FatFsType::Fat12 {
+                // The last entry of the block may continue on the next block.
+                let last_offset = u64::from(Cluster(end_cluster - 1).to_fat_offset(fat_type));
+                if (last_offset + 1) / block_size > block_index {
+                    fat_cache.read(
+                        fs,
+                        (block_index + 1) * block_size,
+                        &mut block[crate::MINIMAL_BLOCK_SIZE..],
+                    )?;
+                }
+            }
+        }
+
+        let value = FatValue::from_raw(
+            fat_type,
+            Cluster(index),
+            &block[block_offset..block_offset + raw_size],
+        );
+
+        if (value == FatValue::Free) == free {
+            return Ok(Some(Cluster(index)));
+        }
+    }
+
+    Ok(None)
+}
+
 /// Compute the whole cluster count of a given FileSystem.
+/// NOTE: The FAT is scanned a block at a time, a run of free clusters at a time.
 pub fn get_free_cluster_count<S: StorageDevice>(fs: &FatFileSystem<S>) -> FatFileSystemResult<u32> {
+    // On exFAT, the allocation state is kept in the allocation bitmap.
+    if fs.boot_record.fat_type == FatFsType::ExFat {
+        return fs.allocation_bitmap.get_free_cluster_count(fs);
+    }
+
+    let end_cluster = fs.boot_record.cluster_count;
     let mut current_cluster = Cluster(2);
 
     let mut res = 0;
 
-    while current_cluster.0 < fs.boot_record.cluster_count {
-        if let FatValue::Free = FatValue::get(fs, current_cluster)? {
-            res += 1;
-        }
+    while let Some(free_cluster) = find_cluster(fs, current_cluster, end_cluster, true)? {
+        let used_cluster =
+            find_cluster(fs, free_cluster, end_cluster, false)?.unwrap_or(Cluster(end_cluster));
 
-        current_cluster = Cluster(current_cluster.0 + 1);
+        res += used_cluster.0 - free_cluster.0;
+        current_cluster = used_cluster;
     }
 
     Ok(res)
@@ -0,0 +1,146 @@
+//! exFAT up-case table.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::cluster::Cluster;
+use super::filesystem::FatFileSystem;
+use super::table::FatClusterIter;
+use super::utils::FileSystemIterator;
+use super::FatError;
+use super::FatFileSystemResult;
+use storage_device::StorageDevice;
+
+/// Represent the exFAT up-case table.
+/// Only the characters that aren't mapped to themselves are kept, the other characters of the 0..=0xFFFF range are mapped to themselves.
+pub(crate) struct UpcaseTable {
+    /// The characters that aren't mapped to themselves and their up-case representation, sorted by character.
+    mappings: [(u16, u16); UpcaseTable::MAX_MAPPINGS],
+
+    /// The count of mappings used in ``mappings``.
+    len: usize,
+}
+
+impl UpcaseTable {
+    /// The max count of characters not mapped to themselves kept in memory.
+    /// NOTE: Less than 1500 characters of the 0..=0xFFFF range have an up-case representation other than themselves.
+    const MAX_MAPPINGS: usize = 2048;
+
+    /// Marker of a compressed run of characters mapped to themselves.
+    const IDENTITY_RUN_MARKER: u16 = 0xFFFF;
+
+    /// Create an empty up-case table mapping every characters to themselves.
+    pub fn empty() -> Self {
+        UpcaseTable {
+            mappings: [(0x0, 0x0); UpcaseTable::MAX_MAPPINGS],
+            len: 0,
+        }
+    }
+
+    /// Load the up-case table stored at the given cluster and check its checksum.
+    /// Both the compressed and the uncompressed forms of the table are supported.
+    /// NOTE: A table mapping more than ``MAX_MAPPINGS`` characters to another character is refused.
+    pub fn load<S: StorageDevice>(
+        fs: &FatFileSystem<S>,
+        start_cluster: Cluster,
+        length: u64,
+        checksum: u32,
+    ) -> FatFileSystemResult<Self> {
+        let mut res = Self::empty();
+        let cluster_size = u64::from(fs.boot_record.bytes_per_cluster());
+
+        let mut block = [0x0u8; crate::MINIMAL_BLOCK_SIZE];
+        let mut computed_checksum = 0u32;
+        let mut read_size = 0;
+
+        // The character described by the next entry and whether the next entry is the length of an identity run.
+        let mut current_char = 0u32;
+        let mut is_run_len = false;
+
+        let mut cluster_iter = FatClusterIter::new(fs, start_cluster);
+        while let Some(cluster) = cluster_iter.next(fs) {
+            let mut cluster_offset = 0;
+            while cluster_offset < cluster_size && read_size < length {
+                fs.storage_device
+                    .lock()
+                    .read(
+                        fs.partition_start + cluster.to_data_bytes_offset(fs) + cluster_offset,
+                        &mut block,
+                    )
+                    .or(Err(FatError::ReadFailed))?;
+
+                let block_len = core::cmp::min(block.len() as u64, length - read_size) as usize;
+                for value in block[..block_len].iter() {
+                    computed_checksum = computed_checksum
+                        .rotate_right(1)
+                        .wrapping_add(u32::from(*value));
+                }
+
+                for entry in block[..block_len].chunks(2) {
+                    if entry.len() != 2 {
+                        continue;
+                    }
+
+                    let value = LittleEndian::read_u16(entry);
+
+                    if is_run_len {
+                        current_char += u32::from(value);
+                        is_run_len = false;
+                    } else if value == Self::IDENTITY_RUN_MARKER {
+                        is_run_len = true;
+                    } else {
+                        if current_char <= 0xFFFF && u32::from(value) != current_char {
+                            res.push(current_char as u16, value)?;
+                        }
+
+                        current_char += 1;
+                    }
+                }
+
+                read_size += block_len as u64;
+                cluster_offset += block.len() as u64;
+            }
+        }
+
+        if read_size != length || computed_checksum != checksum {
+            return Err(FatError::InvalidPartition);
+        }
+
+        Ok(res)
+    }
+
+    /// Add a character that isn't mapped to itself.
+    /// NOTE: The characters must be added in increasing order.
+    fn push(&mut self, c: u16, upper: u16) -> FatFileSystemResult<()> {
+        if self.len == Self::MAX_MAPPINGS {
+            return Err(FatError::Custom {
+                name: "up-case table too large",
+            });
+        }
+
+        self.mappings[self.len] = (c, upper);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Convert a UTF-16 character to its up-case representation.
+    pub fn to_upper(&self, c: u16) -> u16 {
+        match self.mappings[..self.len].binary_search_by_key(&c, |mapping| mapping.0) {
+            Ok(index) => self.mappings[index].1,
+            Err(_) => c,
+        }
+    }
+
+    /// Compute the exFAT name hash of the given UTF-16 name.
+    pub fn name_hash(&self, name: &[u16]) -> u16 {
+        let mut hash = 0u16;
+
+        for c in name {
+            let c = self.to_upper(*c);
+            hash = hash.rotate_right(1).wrapping_add(c & 0xFF);
+            hash = hash.rotate_right(1).wrapping_add(c >> 8);
+        }
+
+        hash
+    }
+}
@@ -0,0 +1,289 @@
+//! Cursor based file handle.
+
+use super::FileOperations;
+use super::FileSystemError;
+use super::FileSystemResult;
+
+/// Represent the possible ways to move the cursor of a file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SeekFrom {
+    /// Set the cursor to the given offset from the start of the file.
+    Start(u64),
+
+    /// Set the cursor to the size of the file plus the given offset.
+    End(i64),
+
+    /// Set the cursor to the current position plus the given offset.
+    Current(i64),
+}
+
+/// A file handle keeping track of the current position in the file.
+/// Every read or write happens at the current position and moves it forward.
+pub struct FileCursor<F: FileOperations> {
+    /// The file accessed by the cursor.
+    file: F,
+
+    /// The current position in the file.
+    position: u64,
+}
+
+impl<F: FileOperations> FileCursor<F> {
+    /// Create a new cursor at the start of the given file.
+    pub fn new(file: F) -> Self {
+        FileCursor { file, position: 0 }
+    }
+
+    /// Return the current position in the file.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Return a mutable reference to the underlying file.
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.file
+    }
+
+    /// Release the underlying file.
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+
+    /// Read the content of the file at the current position in ``buf`` and move the cursor after the data read.
+    /// Return the count of bytes read, 0 if the cursor is at the end of the file.
+    pub fn read(&mut self, buf: &mut [u8]) -> FileSystemResult<u64> {
+        let read_size = self.file.read(self.position, buf)?;
+        self.position = self
+            .position
+            .checked_add(read_size)
+            .ok_or(FileSystemError::InvalidInput)?;
+
+        Ok(read_size)
+    }
+
+    /// Write the given ``buf`` at the current position in the file and move the cursor after the data written.
+    /// NOTE: Writing after the end of the file follows the rules of ``FileOperations::write``.
+    /// NOTE: A write ending after the last representable position returns a FileSystemError::InvalidInput.
+    pub fn write(&mut self, buf: &[u8]) -> FileSystemResult<()> {
+        let position = self
+            .position
+            .checked_add(buf.len() as u64)
+            .ok_or(FileSystemError::InvalidInput)?;
+
+        self.file.write(self.position, buf)?;
+        self.position = position;
+
+        Ok(())
+    }
+
+    /// Move the cursor to the given position and return the new position from the start of the file.
+    /// Moving the cursor before the start of the file returns a FileSystemError::InvalidInput.
+    pub fn seek(&mut self, position: SeekFrom) -> FileSystemResult<u64> {
+        let (base, offset) = match position {
+            SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.file.get_len()?, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+
+        let position = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.wrapping_neg() as u64)
+        };
+
+        self.position = position.ok_or(FileSystemError::InvalidInput)?;
+        Ok(self.position)
+    }
+
+    /// Flush any data not written on the filesystem.
+    pub fn flush(&mut self) -> FileSystemResult<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+mod std_io {
+    use super::FileCursor;
+    use super::SeekFrom;
+    use crate::FileOperations;
+    use crate::FileSystemError;
+
+    use std::format;
+    use std::io;
+
+    impl From<FileSystemError> for io::Error {
+        fn from(error: FileSystemError) -> io::Error {
+            let kind = match error {
+                FileSystemError::NotFound => io::ErrorKind::NotFound,
+                FileSystemError::AccessDenied => io::ErrorKind::PermissionDenied,
+                FileSystemError::FileExists => io::ErrorKind::AlreadyExists,
+                FileSystemError::InvalidInput => io::ErrorKind::InvalidInput,
+                _ => io::ErrorKind::Other,
+            };
+
+            io::Error::new(kind, format!("{:?}", error))
+        }
+    }
+
+    impl<F: FileOperations> io::Read for FileCursor<F> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            Ok(FileCursor::read(self, buf)? as usize)
+        }
+    }
+
+    impl<F: FileOperations> io::Write for FileCursor<F> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            FileCursor::write(self, buf)?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(FileCursor::flush(self)?)
+        }
+    }
+
+    impl<F: FileOperations> io::Seek for FileCursor<F> {
+        fn seek(&mut self, position: io::SeekFrom) -> io::Result<u64> {
+            let position = match position {
+                io::SeekFrom::Start(offset) => SeekFrom::Start(offset),
+                io::SeekFrom::End(offset) => SeekFrom::End(offset),
+                io::SeekFrom::Current(offset) => SeekFrom::Current(offset),
+            };
+
+            Ok(FileCursor::seek(self, position)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileCursor;
+    use super::SeekFrom;
+    use crate::FileOperations;
+    use crate::FileSystemError;
+    use crate::FileSystemResult;
+
+    /// A file without content only reporting a given length.
+    struct EmptyFile {
+        /// The length of the file.
+        len: u64,
+    }
+
+    impl FileOperations for EmptyFile {
+        fn read(&mut self, _offset: u64, _buf: &mut [u8]) -> FileSystemResult<u64> {
+            Ok(0)
+        }
+
+        fn write(&mut self, _offset: u64, _buf: &[u8]) -> FileSystemResult<()> {
+            Err(FileSystemError::AccessDenied)
+        }
+
+        fn flush(&mut self) -> FileSystemResult<()> {
+            Ok(())
+        }
+
+        fn set_len(&mut self, size: u64) -> FileSystemResult<()> {
+            self.len = size;
+            Ok(())
+        }
+
+        fn get_len(&mut self) -> FileSystemResult<u64> {
+            Ok(self.len)
+        }
+    }
+
+    /// The smallest offset a seek can be given.
+    const MIN_OFFSET: i64 = -0x8000_0000_0000_0000;
+
+    /// Create a cursor over a file of 100 bytes.
+    fn cursor() -> FileCursor<EmptyFile> {
+        FileCursor::new(EmptyFile { len: 100 })
+    }
+
+    #[test]
+    fn seek_from_start() {
+        let mut cursor = cursor();
+        assert_eq!(cursor.position(), 0);
+
+        assert_eq!(cursor.seek(SeekFrom::Start(42)).unwrap(), 42);
+        assert_eq!(cursor.position(), 42);
+
+        // Moving after the end of the file is allowed.
+        assert_eq!(cursor.seek(SeekFrom::Start(1000)).unwrap(), 1000);
+        assert_eq!(cursor.seek(SeekFrom::Start(!0)).unwrap(), !0);
+    }
+
+    #[test]
+    fn seek_from_current() {
+        let mut cursor = cursor();
+        cursor.seek(SeekFrom::Start(10)).unwrap();
+
+        assert_eq!(cursor.seek(SeekFrom::Current(5)).unwrap(), 15);
+        assert_eq!(cursor.seek(SeekFrom::Current(-15)).unwrap(), 0);
+        assert_eq!(cursor.seek(SeekFrom::Current(0)).unwrap(), 0);
+        assert_eq!(cursor.seek(SeekFrom::Current(200)).unwrap(), 200);
+    }
+
+    #[test]
+    fn seek_from_end() {
+        let mut cursor = cursor();
+
+        assert_eq!(cursor.seek(SeekFrom::End(0)).unwrap(), 100);
+        assert_eq!(cursor.seek(SeekFrom::End(-100)).unwrap(), 0);
+        assert_eq!(cursor.seek(SeekFrom::End(-1)).unwrap(), 99);
+        assert_eq!(cursor.seek(SeekFrom::End(10)).unwrap(), 110);
+
+        // The length is read again on every seek.
+        cursor.get_mut().set_len(50).unwrap();
+        assert_eq!(cursor.seek(SeekFrom::End(0)).unwrap(), 50);
+    }
+
+    #[test]
+    fn seek_out_of_range() {
+        let mut cursor = cursor();
+        cursor.seek(SeekFrom::Start(10)).unwrap();
+
+        // Before the start of the file.
+        match cursor.seek(SeekFrom::Current(-11)) {
+            Err(FileSystemError::InvalidInput) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match cursor.seek(SeekFrom::End(-101)) {
+            Err(FileSystemError::InvalidInput) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match cursor.seek(SeekFrom::End(MIN_OFFSET)) {
+            Err(FileSystemError::InvalidInput) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // After the largest position.
+        cursor.seek(SeekFrom::Start(!0)).unwrap();
+        match cursor.seek(SeekFrom::Current(1)) {
+            Err(FileSystemError::InvalidInput) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // A failed seek doesn't move the cursor.
+        assert_eq!(cursor.position(), !0);
+        assert_eq!(
+            cursor.seek(SeekFrom::Current(MIN_OFFSET)).unwrap(),
+            (1 << 63) - 1
+        );
+    }
+
+    #[test]
+    fn write_out_of_range() {
+        let mut cursor = cursor();
+        cursor.seek(SeekFrom::Start(!0)).unwrap();
+
+        // The end of the write isn't representable, the file isn't reached.
+        match cursor.write(&[0x0; 1]) {
+            Err(FileSystemError::InvalidInput) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(cursor.position(), !0);
+    }
+}
@@ -3,11 +3,16 @@
 
 extern crate alloc;
 
+#[cfg(feature = "std")]
+extern crate std;
+
 #[macro_use]
 extern crate bitflags;
 
 use alloc::boxed::Box;
 
+pub mod cursor;
+
 /// Represent a filesystem error.
 #[derive(Debug)]
 pub enum FileSystemError {
@@ -46,6 +51,9 @@ pub enum FileSystemError {
 
     /// The partition wasn't used as it's invalid.
     InvalidPartition,
+
+    /// The given argument is invalid.
+    InvalidInput,
 }
 
 /// Represent the type of a given resource when walking a directory.
@@ -85,6 +93,16 @@ bitflags! {
 
         // The file should be appendable.
         const APPENDABLE = 0b0000_0100;
+
+        // The file should be created if it doesn't exist.
+        const CREATE = 0b0000_1000;
+
+        // The file should be truncated to zero size, this requires the file to be writable.
+        const TRUNCATE = 0b0001_0000;
+
+        // The file should be created and opening an existing file should fail, used with CREATE.
+        // NOTE: Using it without CREATE is an invalid input.
+        const EXCLUSIVE = 0b0010_0000;
     }
 }
 
@@ -102,6 +120,23 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Flags indicating the attributes of a resource.
+    pub struct FileAttributes: u32 {
+        /// The resource shouldn't be modified.
+        const READ_ONLY = 0b0000_0001;
+
+        /// The resource should be hidden from normal directory views.
+        const HIDDEN = 0b0000_0010;
+
+        /// The resource belongs to the system.
+        const SYSTEM = 0b0000_0100;
+
+        /// The resource was modified since it was last backed up.
+        const ARCHIVE = 0b0000_1000;
+    }
+}
+
 /// Represent the attached timestamps on a given resource.
 #[derive(Debug)]
 pub struct FileTimeStampRaw {
@@ -118,6 +153,55 @@ pub struct FileTimeStampRaw {
     pub is_valid: bool,
 }
 
+/// Represent the metadata of a given resource.
+#[derive(Debug)]
+pub struct FileMetadata {
+    /// The type of the resource.
+    pub entry_type: DirectoryEntryType,
+
+    /// The size of the file. (0 if it's a directory)
+    pub file_size: u64,
+
+    /// The resource creation UNIX timestamp.
+    pub creation_timestamp: u64,
+
+    /// The resource last modification UNIX timestamp.
+    pub modified_timestamp: u64,
+
+    /// The resource last access UNIX timestamp.
+    pub accessed_timestamp: u64,
+
+    /// The raw attributes of the resource as stored by the filesystem.
+    pub attributes: u32,
+}
+
+/// Represent the informations of a volume.
+#[derive(Debug)]
+pub struct VolumeInfo {
+    /// The total size of the volume in bytes.
+    pub total_size: u64,
+
+    /// The free space on the volume in bytes.
+    pub free_size: u64,
+
+    /// The size of an allocation unit in bytes.
+    pub cluster_size: u64,
+
+    /// The name of the filesystem type. (e.g. "FAT32")
+    pub filesystem_type: &'static str,
+
+    /// The volume label. (UTF-8, padded with zeros)
+    pub volume_label: [u8; Self::LABEL_LEN],
+
+    /// The volume serial number.
+    pub volume_serial: u32,
+}
+
+impl VolumeInfo {
+    /// Represent the max volume label size (in bytes) supported.
+    pub const LABEL_LEN: usize = 0x2C;
+}
+
 /// Represent a filesystem result.
 pub type FileSystemResult<T> = core::result::Result<T, FileSystemError>;
 
@@ -142,6 +226,50 @@ pub trait FileOperations {
     fn get_len(&mut self) -> FileSystemResult<u64>;
 }
 
+impl<F: FileOperations + ?Sized> FileOperations for Box<F> {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> FileSystemResult<u64> {
+        (**self).read(offset, buf)
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> FileSystemResult<()> {
+        (**self).write(offset, buf)
+    }
+
+    fn flush(&mut self) -> FileSystemResult<()> {
+        (**self).flush()
+    }
+
+    fn set_len(&mut self, size: u64) -> FileSystemResult<()> {
+        (**self).set_len(size)
+    }
+
+    fn get_len(&mut self) -> FileSystemResult<u64> {
+        (**self).get_len()
+    }
+}
+
+impl<F: FileOperations + ?Sized> FileOperations for &mut F {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> FileSystemResult<u64> {
+        (**self).read(offset, buf)
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> FileSystemResult<()> {
+        (**self).write(offset, buf)
+    }
+
+    fn flush(&mut self) -> FileSystemResult<()> {
+        (**self).flush()
+    }
+
+    fn set_len(&mut self, size: u64) -> FileSystemResult<()> {
+        (**self).set_len(size)
+    }
+
+    fn get_len(&mut self) -> FileSystemResult<u64> {
+        (**self).get_len()
+    }
+}
+
 /// Represent the operation on a directory.
 pub trait DirectoryOperations {
     /// Read the next directory entries and return the number of entries read.
@@ -187,4 +315,49 @@ pub trait FileSystemOperations {
 
     /// Return the attached timestamps on a resource at the given ``path``.
     fn get_file_timestamp_raw(&self, path: &str) -> FileSystemResult<FileTimeStampRaw>;
+
+    /// Return the metadata of a resource at the given ``path``.
+    /// NOTE: The default implementation returns FileSystemError::Unknown.
+    fn metadata(&self, _path: &str) -> FileSystemResult<FileMetadata> {
+        Err(FileSystemError::Unknown)
+    }
+
+    /// Set the attached timestamps on a resource at the given ``path``.
+    /// The timestamps are rounded to the resolution supported by the filesystem, ``is_valid`` is ignored.
+    /// NOTE: The default implementation returns FileSystemError::Unknown.
+    fn set_file_timestamp_raw(
+        &self,
+        _path: &str,
+        _timestamp: &FileTimeStampRaw,
+    ) -> FileSystemResult<()> {
+        Err(FileSystemError::Unknown)
+    }
+
+    /// Return the attributes of a resource at the given ``path``.
+    /// NOTE: The default implementation returns FileSystemError::Unknown.
+    fn get_file_attributes(&self, _path: &str) -> FileSystemResult<FileAttributes> {
+        Err(FileSystemError::Unknown)
+    }
+
+    /// Set the attributes of a resource at the given ``path``.
+    /// NOTE: The default implementation returns FileSystemError::Unknown.
+    fn set_file_attributes(
+        &self,
+        _path: &str,
+        _attributes: FileAttributes,
+    ) -> FileSystemResult<()> {
+        Err(FileSystemError::Unknown)
+    }
+
+    /// Return the size, free space, cluster size, type, label and serial number of the volume.
+    /// NOTE: The default implementation returns FileSystemError::Unknown.
+    fn volume_info(&self) -> FileSystemResult<VolumeInfo> {
+        Err(FileSystemError::Unknown)
+    }
+
+    /// Write back all the pending modifications of the filesystem to the storage device.
+    /// NOTE: The default implementation returns FileSystemError::Unknown.
+    fn sync(&self) -> FileSystemResult<()> {
+        Err(FileSystemError::Unknown)
+    }
 }
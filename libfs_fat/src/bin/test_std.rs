@@ -132,15 +132,14 @@ fn dump_to_file_buffered<'a>(
 ) -> FileSystemResult<()> {
     let mut f = File::create(path).unwrap();
 
-    let mut offset = 0;
+    let mut cursor = cursor::FileCursor::new(file);
 
     loop {
-        let read_size = file.read(offset as u64, buffer)? as usize;
+        let read_size = cursor.read(buffer)? as usize;
         f.write_all(&buffer[0..read_size]).unwrap();
         if read_size == 0 {
             break;
         }
-        offset += read_size;
     }
 
     Ok(())
@@ -177,11 +176,13 @@ fn main() -> FileSystemResult<()> {
 
     print_dir(&filesystem, "/", 0, false)?;
 
-    filesystem.create_file("/LICENSE-APACHE", 0)?;
-
     let mut file = filesystem.open_file(
         "/LICENSE-APACHE",
-        FileModeFlags::READABLE | FileModeFlags::WRITABLE | FileModeFlags::APPENDABLE,
+        FileModeFlags::READABLE
+            | FileModeFlags::WRITABLE
+            | FileModeFlags::APPENDABLE
+            | FileModeFlags::CREATE
+            | FileModeFlags::TRUNCATE,
     )?;
     dump_to_filesystem(&mut file, "LICENSE-APACHE")?;
 
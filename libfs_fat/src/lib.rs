@@ -10,15 +10,18 @@ use storage_device::StorageDevice;
 
 use libfs::FileSystemResult;
 use libfs::{
-    DirFilterFlags, DirectoryEntry, DirectoryEntryType, DirectoryOperations, FileModeFlags,
-    FileOperations, FileSystemError, FileSystemOperations, FileTimeStampRaw,
+    DirFilterFlags, DirectoryEntry, DirectoryEntryType, DirectoryOperations, FileAttributes,
+    FileMetadata, FileModeFlags, FileOperations, FileSystemError, FileSystemOperations,
+    FileTimeStampRaw, VolumeInfo,
 };
 
+use libfat::attribute::Attributes;
 use libfat::directory::dir_entry::DirectoryEntry as FatDirectoryEntry;
 use libfat::directory::dir_entry_iterator::DirectoryEntryIterator as FatDirectoryEntryIterator;
 use libfat::directory::File;
 use libfat::FatError;
 use libfat::FatFileSystemResult;
+use libfat::FatFsType;
 use libfat::FileSystemIterator;
 
 #[allow(unused_imports)]
@@ -83,7 +86,8 @@ struct FileInterface<'a, S: StorageDevice> {
 /// A wrapper arround libfat ``FatFileSystem`` implementing ``FileSystemOperations``.
 pub struct FatFileSystem<S: StorageDevice> {
     /// libfat filesystem interface.
-    inner: libfat::filesystem::FatFileSystem<S>,
+    /// NOTE: This is only None once the filesystem is unmounted.
+    inner: Option<libfat::filesystem::FatFileSystem<S>>,
 }
 
 /// Predicate helper used to filter directory entries.
@@ -132,12 +136,26 @@ impl DirectoryFilterPredicate {
 }
 
 impl<S: StorageDevice> FatFileSystem<S> {
+    /// Get the libfat filesystem.
+    fn inner(&self) -> &libfat::filesystem::FatFileSystem<S> {
+        self.inner
+            .as_ref()
+            .expect("the filesystem is already unmounted")
+    }
+
+    /// Get the libfat filesystem mutably.
+    fn inner_mut(&mut self) -> &mut libfat::filesystem::FatFileSystem<S> {
+        self.inner
+            .as_mut()
+            .expect("the filesystem is already unmounted")
+    }
+
     /// Helper used to open a directory using the root directory.
     fn get_dir_from_path(
         &self,
         path: &str,
     ) -> FileSystemResult<libfat::directory::Directory<'_, S>> {
-        self.inner
+        self.inner()
             .open_directory(path)
             .map_err(FileSystemError::from_driver)
     }
@@ -147,46 +165,95 @@ impl<S: StorageDevice> FatFileSystem<S> {
         let inner_fs =
             libfat::get_raw_partition(storage_device).map_err(FileSystemError::from_driver)?;
 
-        Ok(FatFileSystem { inner: inner_fs })
+        Ok(FatFileSystem {
+            inner: Some(inner_fs),
+        })
+    }
+
+    /// Set the clock used to stamp the datetimes of the entries.
+    pub fn set_clock(&mut self, clock: &'static dyn libfat::datetime::Clock) {
+        self.inner_mut().set_clock(clock);
+    }
+
+    /// Allow or refuse the modification of entries with the read only attribute.
+    pub fn set_read_only_override(&mut self, read_only_override: bool) {
+        self.inner_mut().set_read_only_override(read_only_override);
+    }
+
+    /// Grow the file at the given path to ``size`` bytes, allocating the missing clusters as one physically contiguous run.
+    /// NOTE: This does nothing if the file is already larger than ``size``.
+    pub fn preallocate(&self, path: &str, size: u64) -> FileSystemResult<()> {
+        let mut file = self
+            .inner()
+            .open_file(path)
+            .map_err(FileSystemError::from_driver)?;
+
+        file.preallocate(self.inner(), size)
+            .map_err(FileSystemError::from_driver)
+    }
+
+    /// Write back all the pending modifications, mark the volume as cleanly unmounted and release the storage device.
+    /// NOTE: The storage device is released even if the modifications cannot be written back, the error is returned along with it.
+    pub fn unmount(mut self) -> (S, FileSystemResult<()>) {
+        // Drop doesn't flush the filesystem once it is moved out.
+        let inner = self
+            .inner
+            .take()
+            .expect("the filesystem is already unmounted");
+
+        let (storage_device, result) = inner.unmount();
+        (storage_device, result.map_err(FileSystemError::from_driver))
+    }
+}
+
+impl<S: StorageDevice> Drop for FatFileSystem<S> {
+    fn drop(&mut self) {
+        // Write back the FAT cache before releasing the storage device.
+        // NOTE: The volume is left dirty, only an explicit sync or unmount marks it as cleanly unmounted.
+        // NOTE: Nothing is left to write back once the filesystem is unmounted.
+        if let Some(ref inner) = self.inner {
+            if let Err(error) = inner.flush() {
+                error!("Cannot flush the filesystem: {:?}", error);
+            }
+        }
     }
 }
 
 impl<S: StorageDevice> FileSystemOperations for FatFileSystem<S> {
     fn create_file(&self, path: &str, size: u64) -> FileSystemResult<()> {
-        self.inner
-            .create_file(path)
-            .map_err(FileSystemError::from_driver)?;
-
-        let mut file = self.open_file(path, FileModeFlags::APPENDABLE)?;
+        let mut file = self.open_file(
+            path,
+            FileModeFlags::APPENDABLE | FileModeFlags::CREATE | FileModeFlags::EXCLUSIVE,
+        )?;
         file.set_len(size)
     }
 
     fn create_directory(&self, path: &str) -> FileSystemResult<()> {
-        self.inner
+        self.inner()
             .create_directory(path)
             .map_err(FileSystemError::from_driver)
     }
 
     fn rename_file(&self, old_path: &str, new_path: &str) -> FileSystemResult<()> {
-        self.inner
+        self.inner()
             .rename_file(old_path, new_path)
             .map_err(FileSystemError::from_driver)
     }
 
     fn rename_directory(&self, old_path: &str, new_path: &str) -> FileSystemResult<()> {
-        self.inner
+        self.inner()
             .rename_directory(old_path, new_path)
             .map_err(FileSystemError::from_driver)
     }
 
     fn delete_file(&self, path: &str) -> FileSystemResult<()> {
-        self.inner
+        self.inner()
             .delete_file(path)
             .map_err(FileSystemError::from_driver)
     }
 
     fn delete_directory(&self, path: &str) -> FileSystemResult<()> {
-        self.inner
+        self.inner()
             .delete_directory(path)
             .map_err(FileSystemError::from_driver)
     }
@@ -196,13 +263,32 @@ impl<S: StorageDevice> FileSystemOperations for FatFileSystem<S> {
         path: &str,
         mode: FileModeFlags,
     ) -> FileSystemResult<Box<dyn FileOperations + 'a>> {
-        let file_entry = self
-            .inner
-            .open_file(path)
-            .map_err(FileSystemError::from_driver)?;
+        let is_truncate = (mode & FileModeFlags::TRUNCATE) == FileModeFlags::TRUNCATE;
+        if is_truncate && (mode & FileModeFlags::WRITABLE) != FileModeFlags::WRITABLE {
+            return Err(FileSystemError::AccessDenied);
+        }
+
+        let is_create = (mode & FileModeFlags::CREATE) == FileModeFlags::CREATE;
+        let is_exclusive = (mode & FileModeFlags::EXCLUSIVE) == FileModeFlags::EXCLUSIVE;
+        if is_exclusive && !is_create {
+            return Err(FileSystemError::InvalidInput);
+        }
+
+        let mut file_entry = if is_create {
+            self.inner().open_or_create_file(path, is_exclusive)
+        } else {
+            self.inner().open_file(path)
+        }
+        .map_err(FileSystemError::from_driver)?;
+
+        if is_truncate {
+            file_entry
+                .set_len(self.inner(), 0)
+                .map_err(FileSystemError::from_driver)?;
+        }
 
         let res = Box::new(FileInterface {
-            fs: &self.inner,
+            fs: self.inner(),
             file_inner: file_entry,
             mode,
         });
@@ -235,7 +321,7 @@ impl<S: StorageDevice> FileSystemOperations for FatFileSystem<S> {
 
         let entry_count = target_dir
             .iter()
-            .to_iterator(&self.inner)
+            .to_iterator(self.inner())
             .filter(filter_fn)
             .count() as u64;
 
@@ -257,7 +343,7 @@ impl<S: StorageDevice> FileSystemOperations for FatFileSystem<S> {
         }
 
         let res = Box::new(DirectoryReader {
-            fs: &self.inner,
+            fs: self.inner(),
             base_path: data,
             internal_iter: target_dir_clone.iter(),
             filter_fn,
@@ -269,7 +355,7 @@ impl<S: StorageDevice> FileSystemOperations for FatFileSystem<S> {
 
     fn get_file_timestamp_raw(&self, name: &str) -> FileSystemResult<FileTimeStampRaw> {
         let file_entry = self
-            .inner
+            .inner()
             .search_entry(name)
             .map_err(FileSystemError::from_driver)?;
 
@@ -282,6 +368,117 @@ impl<S: StorageDevice> FileSystemOperations for FatFileSystem<S> {
 
         Ok(result)
     }
+
+    fn metadata(&self, path: &str) -> FileSystemResult<FileMetadata> {
+        let entry = self
+            .inner()
+            .search_entry(path)
+            .map_err(FileSystemError::from_driver)?;
+
+        // On exFAT, the size of a directory is the size of its clusters.
+        let (entry_type, file_size) = if entry.attribute.is_directory() {
+            (DirectoryEntryType::Directory, 0)
+        } else {
+            (DirectoryEntryType::File, entry.file_size)
+        };
+
+        Ok(FileMetadata {
+            entry_type,
+            file_size,
+            creation_timestamp: entry.creation_timestamp,
+            modified_timestamp: entry.last_modification_timestamp,
+            accessed_timestamp: entry.last_access_timestamp,
+            attributes: u32::from(entry.attribute.get_value()),
+        })
+    }
+
+    fn set_file_timestamp_raw(
+        &self,
+        path: &str,
+        timestamp: &FileTimeStampRaw,
+    ) -> FileSystemResult<()> {
+        self.inner()
+            .set_file_timestamp(
+                path,
+                timestamp.creation_timestamp,
+                timestamp.modified_timestamp,
+                timestamp.accessed_timestamp,
+            )
+            .map_err(FileSystemError::from_driver)
+    }
+
+    fn get_file_attributes(&self, path: &str) -> FileSystemResult<FileAttributes> {
+        let entry = self
+            .inner()
+            .search_entry(path)
+            .map_err(FileSystemError::from_driver)?;
+
+        let mut attributes = FileAttributes::empty();
+        attributes.set(FileAttributes::READ_ONLY, entry.attribute.is_read_only());
+        attributes.set(FileAttributes::HIDDEN, entry.attribute.is_hidden());
+        attributes.set(FileAttributes::SYSTEM, entry.attribute.is_system());
+        attributes.set(FileAttributes::ARCHIVE, entry.attribute.is_archive());
+
+        Ok(attributes)
+    }
+
+    fn set_file_attributes(&self, path: &str, attributes: FileAttributes) -> FileSystemResult<()> {
+        let mut value = 0;
+        if attributes.contains(FileAttributes::READ_ONLY) {
+            value |= Attributes::READ_ONLY;
+        }
+        if attributes.contains(FileAttributes::HIDDEN) {
+            value |= Attributes::HIDDEN;
+        }
+        if attributes.contains(FileAttributes::SYSTEM) {
+            value |= Attributes::SYSTEM;
+        }
+        if attributes.contains(FileAttributes::ARCHIVE) {
+            value |= Attributes::ARCHIVE;
+        }
+
+        self.inner()
+            .set_attributes(path, Attributes::new(value))
+            .map_err(FileSystemError::from_driver)
+    }
+
+    fn volume_info(&self) -> FileSystemResult<VolumeInfo> {
+        let fat_volume_info = self
+            .inner()
+            .volume_info()
+            .map_err(FileSystemError::from_driver)?;
+
+        let filesystem_type = match fat_volume_info.fat_type {
+            FatFsType::Fat12 => "FAT12",
+            FatFsType::Fat16 => "FAT16",
+            FatFsType::Fat32 => "FAT32",
+            FatFsType::ExFat => "exFAT",
+        };
+
+        let mut volume_label = [0x0; VolumeInfo::LABEL_LEN];
+        for (index, c) in fat_volume_info
+            .volume_label
+            .as_bytes()
+            .iter()
+            .enumerate()
+            .take(VolumeInfo::LABEL_LEN)
+        {
+            volume_label[index] = *c;
+        }
+
+        Ok(VolumeInfo {
+            total_size: fat_volume_info.total_size,
+            free_size: fat_volume_info.free_size,
+            cluster_size: u64::from(fat_volume_info.cluster_size),
+            filesystem_type,
+            volume_label,
+            volume_serial: fat_volume_info.volume_serial,
+        })
+    }
+
+    fn sync(&self) -> FileSystemResult<()> {
+        self.inner().sync().map_err(FileSystemError::from_driver)
+    }
 }
 
 impl<'a, S: StorageDevice> DirectoryOperations for DirectoryReader<'a, S> {
@@ -346,8 +543,7 @@ impl<'a, S: StorageDevice> FileOperations for FileInterface<'a, S> {
     }
 
     fn flush(&mut self) -> FileSystemResult<()> {
-        // NOP
-        Ok(())
+        self.fs.flush().map_err(FileSystemError::from_driver)
     }
 
     fn set_len(&mut self, size: u64) -> FileSystemResult<()> {
@@ -361,7 +557,7 @@ impl<'a, S: StorageDevice> FileOperations for FileInterface<'a, S> {
     }
 
     fn get_len(&mut self) -> FileSystemResult<u64> {
-        Ok(u64::from(self.file_inner.file_info.file_size))
+        Ok(self.file_inner.file_info.file_size)
     }
 }
 
@@ -373,12 +569,11 @@ impl<'a, S: StorageDevice> DirectoryReader<'a, S> {
     ) -> DirectoryEntry {
         let mut path: [u8; DirectoryEntry::PATH_LEN] = [0x0; DirectoryEntry::PATH_LEN];
 
-        let file_size = fat_dir_entry.file_size;
-
-        let entry_type = if fat_dir_entry.attribute.is_directory() {
-            DirectoryEntryType::Directory
+        // On exFAT, the size of a directory is the size of its clusters.
+        let (entry_type, file_size) = if fat_dir_entry.attribute.is_directory() {
+            (DirectoryEntryType::Directory, 0)
         } else {
-            DirectoryEntryType::File
+            (DirectoryEntryType::File, fat_dir_entry.file_size)
         };
 
         let mut base_index = 0;
@@ -406,7 +601,7 @@ impl<'a, S: StorageDevice> DirectoryReader<'a, S> {
         DirectoryEntry {
             path,
             entry_type,
-            file_size: u64::from(file_size),
+            file_size,
         }
     }
 }